@@ -0,0 +1,9 @@
+use aoc2020::day18repl::Repl;
+use std::io;
+
+// Usage: cargo run --bin day18repl
+// Reads expressions and commands from stdin, so a script can be piped in; :help lists them
+pub fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let stdin = io::stdin();
+    Repl::new().run(stdin.lock(), io::stdout())
+}
@@ -0,0 +1,16 @@
+use aoc2020::day8asm::assemble;
+use aoc2020::day8debugger::Debugger;
+use std::io;
+
+// Usage: cargo run --bin day8debugger [program file]
+// Defaults to the puzzle input, labels and comments are allowed (see day8asm)
+// Commands are read from stdin, so a script can be piped in
+pub fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let path = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| String::from("input/2020/day8.txt"));
+    let program = assemble(&std::fs::read_to_string(path)?)?;
+    let mut dbg = Debugger::new(&program);
+    let stdin = io::stdin();
+    dbg.run(stdin.lock(), io::stdout())
+}
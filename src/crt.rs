@@ -0,0 +1,180 @@
+use std::error::Error;
+use std::fmt;
+
+// Chinese remainder theorem for any moduli, coprime or not
+// Everything is i128 and every step that could overflow is checked, so big systems fail loudly
+// instead of wrapping around into a wrong answer
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CrtError {
+    // The congruences contradict each other
+    NoSolution,
+    // The combined modulus does not fit into an i128
+    Overflow,
+    NonPositiveModulus(i128),
+}
+
+impl fmt::Display for CrtError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CrtError::NoSolution => write!(f, "the congruences have no common solution"),
+            CrtError::Overflow => write!(f, "the combined modulus overflows i128"),
+            CrtError::NonPositiveModulus(m) => write!(f, "modulus must be positive, got {}", m),
+        }
+    }
+}
+
+impl Error for CrtError {}
+
+// Every solution is value + k * period; value is the smallest non-negative one
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Solution {
+    pub value: i128,
+    pub period: i128,
+}
+
+// Returns (g, x, y) such that a * x + b * y = g = gcd(a, b)
+pub fn ext_gcd(a: i128, b: i128) -> (i128, i128, i128) {
+    let (mut old_r, mut r) = (a, b);
+    let (mut old_s, mut s) = (1, 0);
+    let (mut old_t, mut t) = (0, 1);
+    while r != 0 {
+        let q = old_r / r;
+        let tmp = old_r - q * r;
+        old_r = r;
+        r = tmp;
+        let tmp = old_s - q * s;
+        old_s = s;
+        s = tmp;
+        let tmp = old_t - q * t;
+        old_t = t;
+        t = tmp;
+    }
+    (old_r, old_s, old_t)
+}
+
+// a * b mod m for 0 <= a, b < m, falling back to doubling when the product does not fit
+fn mul_mod(a: i128, b: i128, m: i128) -> i128 {
+    if let Some(p) = a.checked_mul(b) {
+        return p % m;
+    }
+    let (mut a, mut b, mut res) = (a, b, 0i128);
+    while b > 0 {
+        if b & 1 == 1 {
+            // res, a < m, so res + a only overflows if m is close to i128::MAX; subtract first
+            res = if res >= m - a { res - (m - a) } else { res + a };
+        }
+        a = if a >= m - a { a - (m - a) } else { a + a };
+        b >>= 1;
+    }
+    res
+}
+
+// Solve x = r (mod m) for every (r, m) at once
+pub fn solve(congruences: &[(i128, i128)]) -> Result<Solution, CrtError> {
+    congruences.iter().try_fold(
+        Solution {
+            value: 0,
+            period: 1,
+        },
+        |acc, &(r, m)| {
+            if m <= 0 {
+                return Err(CrtError::NonPositiveModulus(m));
+            }
+            merge(acc, r.rem_euclid(m), m)
+        },
+    )
+}
+
+// Fold one more congruence x = r (mod m) into the solution so far
+fn merge(acc: Solution, r: i128, m: i128) -> Result<Solution, CrtError> {
+    let (g, p, _) = ext_gcd(acc.period, m);
+    let diff = r - acc.value;
+    if diff % g != 0 {
+        return Err(CrtError::NoSolution);
+    }
+    // acc.value + acc.period * t = r (mod m), so t = diff / g * p (mod m / g)
+    let mg = m / g;
+    let t = mul_mod((diff / g).rem_euclid(mg), p.rem_euclid(mg), mg);
+    let period = acc.period.checked_mul(mg).ok_or(CrtError::Overflow)?;
+    let value = acc
+        .period
+        .checked_mul(t)
+        .and_then(|v| v.checked_add(acc.value))
+        .ok_or(CrtError::Overflow)?;
+    Ok(Solution {
+        value: value.rem_euclid(period),
+        period,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_ext_gcd() {
+        let (g, x, y) = ext_gcd(240, 46);
+        assert_eq!(g, 2);
+        assert_eq!(240 * x + 46 * y, 2);
+    }
+
+    #[test]
+    pub fn test_coprime() {
+        assert_eq!(
+            solve(&[(2, 3), (3, 5), (2, 7)]),
+            Ok(Solution {
+                value: 23,
+                period: 105
+            })
+        );
+        assert_eq!(
+            solve(&[]),
+            Ok(Solution {
+                value: 0,
+                period: 1
+            })
+        );
+    }
+
+    #[test]
+    pub fn test_non_coprime() {
+        // x = 3 (mod 4) and x = 5 (mod 6) share the factor 2 and agree on it
+        assert_eq!(
+            solve(&[(3, 4), (5, 6)]),
+            Ok(Solution {
+                value: 11,
+                period: 12
+            })
+        );
+        // x = 1 (mod 4) and x = 2 (mod 6) disagree on parity
+        assert_eq!(solve(&[(1, 4), (2, 6)]), Err(CrtError::NoSolution));
+        assert_eq!(solve(&[(1, 0)]), Err(CrtError::NonPositiveModulus(0)));
+    }
+
+    #[test]
+    pub fn test_big() {
+        // Two primes close to 2^61: the period is close to 2^122, still well inside an i128
+        let p = 2_305_843_009_213_693_951i128;
+        let q = 2_305_843_009_213_693_921i128;
+        let s = solve(&[(p - 1, p), (5, q)]).unwrap();
+        assert_eq!(s.period, p * q);
+        assert_eq!(s.value % p, p - 1);
+        assert_eq!(s.value % q, 5);
+
+        assert_eq!(
+            solve(&[(0, p), (0, q), (0, 1_000_000_007)]),
+            Err(CrtError::Overflow)
+        );
+
+        // With the Mersenne prime 2^89 - 1 the residues multiplied in merge are close to 2^178,
+        // so this only works through the doubling in mul_mod
+        let m = (1i128 << 89) - 1;
+        assert_eq!(mul_mod(m - 1, m - 1, m), 1);
+        assert_eq!(mul_mod(m - 2, 1 << 60, m), m - (1 << 61));
+        let s = solve(&[(1, 3), (m - 2, m)]).unwrap();
+        assert_eq!(s.period, 3 * m);
+        assert_eq!(s.value % 3, 1);
+        assert_eq!(s.value % m, m - 2);
+    }
+}
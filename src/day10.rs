@@ -1,65 +1,153 @@
 use aoc_runner_derive::{aoc, aoc_generator};
+use num_bigint::BigUint;
+use std::collections::{BTreeMap, BTreeSet};
+use std::num::ParseIntError;
+
+// Which joltage differences an adapter accepts, and how far above the highest adapter the device sits
+#[derive(Debug, Clone, PartialEq)]
+pub struct AdapterRules {
+    pub gaps: BTreeSet<u64>,
+    pub device_offset: u64,
+}
+
+impl Default for AdapterRules {
+    fn default() -> AdapterRules {
+        AdapterRules::new(&[1, 2, 3], 3)
+    }
+}
+
+impl AdapterRules {
+    pub fn new(gaps: &[u64], device_offset: u64) -> AdapterRules {
+        AdapterRules {
+            gaps: gaps.iter().copied().collect(),
+            device_offset,
+        }
+    }
+
+    fn max_gap(&self) -> u64 {
+        self.gaps.iter().next_back().copied().unwrap_or(0)
+    }
+
+    fn allows(&self, from: u64, to: u64) -> bool {
+        to >= from && self.gaps.contains(&(to - from))
+    }
+}
 
 #[aoc_generator(day10)]
-pub fn input_generator(input: &str) -> Vec<u8> {
+pub fn input_generator(input: &str) -> Result<Vec<u64>, ParseIntError> {
     input
         .lines()
-        .map(|l| l.parse::<u8>().unwrap())
-        .collect::<Vec<u8>>()
+        .map(|l| l.parse::<u64>())
+        .collect::<Result<Vec<u64>, ParseIntError>>()
 }
 
-#[aoc(day10, part1)]
-pub fn part1(input: &Vec<u8>) -> u16 {
-    let mut sorted = input.clone();
+// The outlet, every adapter in order and the device
+pub fn chain(input: &[u64], device_offset: u64) -> Vec<u64> {
+    let mut sorted = input.to_vec();
 
     sorted.push(0); // starting point for the jolt socket
+    sorted.push(input.iter().max().copied().unwrap_or(0) + device_offset);
 
     sorted.sort_unstable();
+    sorted
+}
 
-    let (ones, threes) = sorted
-        .iter()
-        .skip(1)
-        .enumerate()
-        .map(|(i, n)| n - sorted[i])
-        .fold((0, 1), |acc: (u16, u16), n| match n {
-            1 => (acc.0 + 1, acc.1),
-            3 => (acc.0, acc.1 + 1),
-            _ => acc,
-        });
-
-    ones * threes
+// How many times each gap shows up when every adapter is used
+pub fn gap_histogram(input: &[u64], device_offset: u64) -> BTreeMap<u64, usize> {
+    let sorted = chain(input, device_offset);
+    sorted.windows(2).fold(BTreeMap::new(), |mut acc, w| {
+        *acc.entry(w[1] - w[0]).or_insert(0) += 1;
+        acc
+    })
 }
 
-#[aoc(day10, part2)]
-pub fn part2(input: &Vec<u8>) -> u128 {
-    let mut sorted = input.clone();
+#[aoc(day10, part1)]
+pub fn part1(input: &[u64]) -> usize {
+    let hist = gap_histogram(input, AdapterRules::default().device_offset);
+    hist.get(&1).unwrap_or(&0) * hist.get(&3).unwrap_or(&0)
+}
 
-    sorted.push(0); // starting point for the jolt socket
-    sorted.push(*input.iter().max().unwrap() + 3);
+// ways[i] is the number of ways to get from the outlet to the i-th element of the chain
+// Only the elements within max_gap below can lead to it, so a short look back is enough
+pub fn count_arrangements(input: &[u64], rules: &AdapterRules) -> BigUint {
+    let sorted = chain(input, rules.device_offset);
+    let mut ways: Vec<BigUint> = vec![BigUint::default(); sorted.len()];
+    ways[0] = BigUint::from(1u8);
+    for i in 1..sorted.len() {
+        let mut j = i;
+        while j > 0 && sorted[i] - sorted[j - 1] <= rules.max_gap() {
+            j -= 1;
+            if rules.allows(sorted[j], sorted[i]) {
+                let w = ways[j].clone();
+                ways[i] += w;
+            }
+        }
+    }
+    ways.pop().unwrap()
+}
 
-    sorted.sort_unstable();
+#[aoc(day10, part2)]
+pub fn part2(input: &[u64]) -> BigUint {
+    count_arrangements(input, &AdapterRules::default())
+}
+
+// Lazily walks every valid arrangement, outlet and device included, in lexicographic order
+// Adapters that can never reach the device are skipped up front, so no time goes into dead ends
+pub struct Arrangements {
+    sorted: Vec<u64>,
+    rules: AdapterRules,
+    can_finish: Vec<bool>,
+    // indices into sorted, each with the next candidate to try after it
+    stack: Vec<(usize, usize)>,
+}
 
-    *sorted
-        .iter()
-        .skip(1)
-        .fold(
-            vec![0u128; (*sorted.iter().max().unwrap() + 1) as usize],
-            |mut acc, &n| {
-                acc[0] = 1; // don't know how to initialize it better
+impl Arrangements {
+    pub fn new(input: &[u64], rules: &AdapterRules) -> Arrangements {
+        let sorted = chain(input, rules.device_offset);
+        let last = sorted.len() - 1;
+        let mut can_finish = vec![false; sorted.len()];
+        can_finish[last] = true;
+        for i in (0..last).rev() {
+            can_finish[i] = (i + 1..sorted.len())
+                .take_while(|&j| sorted[j] - sorted[i] <= rules.max_gap())
+                .any(|j| can_finish[j] && rules.allows(sorted[i], sorted[j]));
+        }
+        let stack = if can_finish[0] { vec![(0, 1)] } else { vec![] };
+        Arrangements {
+            sorted,
+            rules: rules.clone(),
+            can_finish,
+            stack,
+        }
+    }
+}
 
-                let idx = n as usize;
+impl Iterator for Arrangements {
+    type Item = Vec<u64>;
 
-                for &i in [1, 2, 3].iter() {
-                    if idx >= i {
-                        acc[idx] += acc[idx - i];
-                    }
+    fn next(&mut self) -> Option<Vec<u64>> {
+        let last = self.sorted.len() - 1;
+        while let Some(&(i, from)) = self.stack.last() {
+            if i == last {
+                let res = self.stack.iter().map(|&(k, _)| self.sorted[k]).collect();
+                self.stack.pop();
+                return Some(res);
+            }
+            let next = (from..self.sorted.len())
+                .take_while(|&j| self.sorted[j] - self.sorted[i] <= self.rules.max_gap())
+                .find(|&j| self.can_finish[j] && self.rules.allows(self.sorted[i], self.sorted[j]));
+            match next {
+                Some(j) => {
+                    self.stack.last_mut().unwrap().1 = j + 1;
+                    self.stack.push((j, j + 1));
                 }
-
-                acc
-            },
-        )
-        .last()
-        .unwrap()
+                None => {
+                    self.stack.pop();
+                }
+            }
+        }
+        None
+    }
 }
 
 #[cfg(test)]
@@ -113,28 +201,85 @@ mod tests {
     #[test]
     pub fn test_generator() {
         assert_eq!(
-            input_generator(INPUT1),
+            input_generator(INPUT1).unwrap(),
             vec![16, 10, 15, 5, 1, 11, 7, 19, 6, 12, 4]
         );
+        assert_eq!(input_generator("300\n1000").unwrap(), vec![300, 1000]);
+        assert!(input_generator("1\nx").is_err());
     }
 
     #[test]
     pub fn test_part1_input1() {
-        assert_eq!(part1(&input_generator(INPUT1)), 35);
+        assert_eq!(part1(&input_generator(INPUT1).unwrap()), 35);
     }
 
     #[test]
     pub fn test_part1_input2() {
-        assert_eq!(part1(&input_generator(INPUT2)), 220);
+        assert_eq!(part1(&input_generator(INPUT2).unwrap()), 220);
     }
 
     #[test]
     pub fn test_part2_input1() {
-        assert_eq!(part2(&input_generator(INPUT1)), 8);
+        assert_eq!(part2(&input_generator(INPUT1).unwrap()), BigUint::from(8u8));
     }
 
     #[test]
     pub fn test_part2_input2() {
-        assert_eq!(part2(&input_generator(INPUT2)), 19208);
+        assert_eq!(
+            part2(&input_generator(INPUT2).unwrap()),
+            BigUint::from(19208u32)
+        );
+    }
+
+    #[test]
+    pub fn test_gap_histogram() {
+        let hist = gap_histogram(&input_generator(INPUT2).unwrap(), 3);
+        assert_eq!(hist.into_iter().collect::<Vec<_>>(), vec![(1, 22), (3, 10)]);
+        let hist = gap_histogram(&[500, 1000], 7);
+        assert_eq!(hist.into_iter().collect::<Vec<_>>(), vec![(7, 1), (500, 2)]);
+    }
+
+    #[test]
+    pub fn test_other_gaps() {
+        let input = input_generator(INPUT1).unwrap();
+        // Only steps of exactly 1 or 3: the only choice left is 4-5-6-7 or 4-7
+        assert_eq!(
+            count_arrangements(&input, &AdapterRules::new(&[1, 3], 3)),
+            BigUint::from(2u8)
+        );
+        // Allowing gaps of 4 opens up more ways
+        assert!(count_arrangements(&input, &AdapterRules::new(&[1, 2, 3, 4], 3)) > part2(&input));
+        // Nothing gets past the first gap of 1
+        assert_eq!(
+            count_arrangements(&input, &AdapterRules::new(&[2, 3], 3)),
+            BigUint::default()
+        );
+
+        // 200 adapters one jolt apart: way more arrangements than a u128 holds
+        let long = (1..=200).collect::<Vec<u64>>();
+        let count = count_arrangements(&long, &AdapterRules::default());
+        assert!(count > BigUint::from(u128::MAX));
+    }
+
+    #[test]
+    pub fn test_arrangements() {
+        let input = input_generator(INPUT1).unwrap();
+        let all = Arrangements::new(&input, &AdapterRules::default()).collect::<Vec<Vec<u64>>>();
+        assert_eq!(all.len(), 8);
+        assert_eq!(all[0], vec![0, 1, 4, 5, 6, 7, 10, 11, 12, 15, 16, 19, 22]);
+        assert_eq!(all[7], vec![0, 1, 4, 7, 10, 12, 15, 16, 19, 22]);
+
+        // Lazy: the first few of a huge number of arrangements come out straight away
+        let long = (1..=200).collect::<Vec<u64>>();
+        let first = Arrangements::new(&long, &AdapterRules::default())
+            .take(3)
+            .collect::<Vec<Vec<u64>>>();
+        assert_eq!(first[0].len(), 202);
+        assert_eq!(first[1][197..], [197, 198, 200, 203]);
+
+        assert_eq!(
+            Arrangements::new(&input, &AdapterRules::new(&[2, 3], 3)).count(),
+            0
+        );
     }
 }
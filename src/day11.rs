@@ -1,236 +1,357 @@
-use std::mem::swap;
-
 use aoc_runner_derive::{aoc, aoc_generator};
 use array2d::Array2D;
+use simple_error::*;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
 
-#[aoc_generator(day11)]
-pub fn input_generator(input: &str) -> Array2D<char> {
-    Array2D::from_rows(
-        &input
-            .lines()
-            .map(|l| l.chars().collect::<Vec<char>>())
-            .collect::<Vec<Vec<char>>>(),
-    )
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Cell {
+    Floor,
+    Empty,
+    Occupied,
 }
 
-pub fn get_part1_value(a: &Array2D<char>, (row, col): (usize, usize)) -> char {
-    let mut count: u32 = 0;
-    let orig = a[(row, col)];
-    for &row_temp in [row.wrapping_sub(1), row, row + 1].iter() {
-        for &col_temp in [col.wrapping_sub(1), col, col + 1].iter() {
-            if (row_temp, col_temp) != (row, col) {
-                if let Some(el) = a.get(row_temp, col_temp) {
-                    if el == &'X' {
-                        count += 1;
-                    }
-                }
-            }
+impl Cell {
+    pub fn from_char(c: char) -> Result<Cell, Box<dyn Error>> {
+        match c {
+            '.' => Ok(Cell::Floor),
+            'L' => Ok(Cell::Empty),
+            '#' => Ok(Cell::Occupied),
+            _ => bail!("unknown seat layout character: {}", c),
         }
     }
-    match orig {
-        'L' => {
-            if count == 0 {
-                'X'
-            } else {
-                'L'
-            }
-        }
-        'X' => {
-            if count >= 4 {
-                'L'
-            } else {
-                'X'
-            }
+}
+
+impl fmt::Display for Cell {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Cell::Floor => write!(f, "."),
+            Cell::Empty => write!(f, "L"),
+            Cell::Occupied => write!(f, "#"),
         }
-        _ => orig,
     }
 }
 
-#[aoc(day11, part1)]
-pub fn part1(input: &Array2D<char>) -> usize {
-    let mut source = input.clone();
-    let mut dest = Array2D::filled_with('.', input.num_rows(), input.num_columns());
-
-    while source != dest {
-        for row_idx in 0..source.num_rows() {
-            for col_idx in 0..source.num_columns() {
-                dest[(row_idx, col_idx)] = get_part1_value(&source, (row_idx, col_idx));
-            }
-        }
-        swap(&mut source, &mut dest);
+#[aoc_generator(day11)]
+pub fn input_generator(input: &str) -> Result<Array2D<Cell>, Box<dyn Error>> {
+    let rows = input
+        .lines()
+        .map(|l| l.chars().map(Cell::from_char).collect())
+        .collect::<Result<Vec<Vec<Cell>>, Box<dyn Error>>>()?;
+    if rows.iter().any(|r| r.len() != rows[0].len()) {
+        bail!("seat layout rows differ in length");
     }
-    dest.elements_row_major_iter()
-        .filter(|&&c| c == 'X')
-        .count()
+    Ok(Array2D::from_rows(&rows))
 }
 
-pub fn get_part2_value(a: &Array2D<char>, (row, col): (usize, usize)) -> char {
-    let mut count: u32 = 0;
-    let orig = a[(row, col)];
-    // We need to check 4 lines:
-    // 1. Horizontal, going across the point
-    // 2. Vertical, going across the point
-    // 3. Down diagonal
-    // 4. Up diagonal
-    // All of them must be split in two parts
-    for row_temp in (0..row).rev() {
-        if let Some(el) = a.get(row_temp, col) {
-            match el {
-                &'X' => {
-                    count += 1;
-                    break;
-                }
-                &'L' => break,
-                _ => continue,
-            }
+pub fn layout_to_string(layout: &Array2D<Cell>) -> String {
+    layout
+        .rows_iter()
+        .map(|r| r.map(|c| c.to_string()).collect::<String>() + "\n")
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Neighbourhood {
+    // The 8 cells around a seat
+    Adjacent,
+    // The first seat seen in each of the 8 directions, looking no further than max_range cells if given
+    LineOfSight { max_range: Option<usize> },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SeatingRules {
+    pub neighbourhood: Neighbourhood,
+    // An occupied seat empties once at least this many neighbours are occupied
+    pub tolerance: usize,
+    // Whether the layout wraps around at the edges
+    pub wrap: bool,
+}
+
+impl SeatingRules {
+    pub fn part1() -> SeatingRules {
+        SeatingRules {
+            neighbourhood: Neighbourhood::Adjacent,
+            tolerance: 4,
+            wrap: false,
         }
     }
-    for row_temp in row + 1..a.num_rows() {
-        if let Some(el) = a.get(row_temp, col) {
-            match el {
-                &'X' => {
-                    count += 1;
-                    break;
-                }
-                &'L' => break,
-                _ => continue,
-            }
+
+    pub fn part2() -> SeatingRules {
+        SeatingRules {
+            neighbourhood: Neighbourhood::LineOfSight { max_range: None },
+            tolerance: 5,
+            wrap: false,
         }
     }
-    for col_temp in (0..col).rev() {
-        // Lala
-        if let Some(el) = a.get(row, col_temp) {
-            match el {
-                &'X' => {
-                    count += 1;
-                    break;
+}
+
+const DIRECTIONS: [(isize, isize); 8] = [
+    (-1, -1),
+    (-1, 0),
+    (-1, 1),
+    (0, -1),
+    (0, 1),
+    (1, -1),
+    (1, 0),
+    (1, 1),
+];
+
+// The seats of a layout and, for every seat, the seats it looks at
+// Floor never changes, so only seats are kept, and the neighbours are worked out once,
+// which makes every generation a single pass over the seats and their neighbour lists
+pub struct SeatGraph {
+    rows: usize,
+    cols: usize,
+    // (row, col) of every seat
+    seats: Vec<(usize, usize)>,
+    neighbours: Vec<Vec<usize>>,
+    tolerance: usize,
+}
+
+impl SeatGraph {
+    pub fn new(layout: &Array2D<Cell>, rules: &SeatingRules) -> SeatGraph {
+        let rows = layout.num_rows();
+        let cols = layout.num_columns();
+        let mut seat_idx: Array2D<Option<usize>> = Array2D::filled_with(None, rows, cols);
+        let mut seats = Vec::new();
+        for row in 0..rows {
+            for col in 0..cols {
+                if layout[(row, col)] != Cell::Floor {
+                    seat_idx[(row, col)] = Some(seats.len());
+                    seats.push((row, col));
                 }
-                &'L' => break,
-                _ => continue,
             }
         }
-    }
-    for col_temp in col + 1..a.num_columns() {
-        // Lala
-        if let Some(el) = a.get(row, col_temp) {
-            match el {
-                &'X' => {
-                    count += 1;
-                    break;
+
+        // Step once from (row, col) in direction (dr, dc), wrapping around or stopping at the edge
+        let step =
+            |(row, col): (usize, usize), (dr, dc): (isize, isize)| -> Option<(usize, usize)> {
+                let r = row as isize + dr;
+                let c = col as isize + dc;
+                if rules.wrap {
+                    Some((
+                        r.rem_euclid(rows as isize) as usize,
+                        c.rem_euclid(cols as isize) as usize,
+                    ))
+                } else if r >= 0 && c >= 0 && (r as usize) < rows && (c as usize) < cols {
+                    Some((r as usize, c as usize))
+                } else {
+                    None
                 }
-                &'L' => break,
-                _ => continue,
-            }
+            };
+
+        let max_range = match rules.neighbourhood {
+            Neighbourhood::Adjacent => Some(1),
+            Neighbourhood::LineOfSight { max_range } => max_range,
+        };
+
+        let neighbours = seats
+            .iter()
+            .map(|&start| {
+                DIRECTIONS
+                    .iter()
+                    .filter_map(|&dir| {
+                        let mut pos = start;
+                        let mut dist = 0;
+                        loop {
+                            if max_range.is_some_and(|m| dist >= m) {
+                                return None;
+                            }
+                            pos = step(pos, dir)?;
+                            dist += 1;
+                            // A wrapped ray that comes back home has seen everything there is to see
+                            if pos == start {
+                                return None;
+                            }
+                            if let Some(idx) = seat_idx[pos] {
+                                return Some(idx);
+                            }
+                        }
+                    })
+                    .collect::<Vec<usize>>()
+            })
+            .collect::<Vec<Vec<usize>>>();
+
+        SeatGraph {
+            rows,
+            cols,
+            seats,
+            neighbours,
+            tolerance: rules.tolerance,
         }
     }
 
-    let mut row_temp = row;
-    let mut col_temp = col;
-
-    while row_temp < a.num_rows() && col_temp < a.num_columns() {
-        row_temp = row_temp.wrapping_sub(1);
-        col_temp = col_temp.wrapping_sub(1);
-        if let Some(el) = a.get(row_temp, col_temp) {
-            match el {
-                &'X' => {
-                    count += 1;
-                    break;
-                }
-                &'L' => break,
-                _ => continue,
-            }
-        }
+    pub fn len(&self) -> usize {
+        self.seats.len()
     }
 
-    row_temp = row;
-    col_temp = col;
-
-    while row_temp < a.num_rows() && col_temp < a.num_columns() {
-        row_temp = row_temp.wrapping_add(1);
-        col_temp = col_temp.wrapping_add(1);
-        if let Some(el) = a.get(row_temp, col_temp) {
-            match el {
-                &'X' => {
-                    count += 1;
-                    break;
-                }
-                &'L' => break,
-                _ => continue,
-            }
-        }
+    pub fn is_empty(&self) -> bool {
+        self.seats.is_empty()
     }
 
-    row_temp = row;
-    col_temp = col;
-
-    while row_temp < a.num_rows() && col_temp < a.num_columns() {
-        row_temp = row_temp.wrapping_sub(1);
-        col_temp = col_temp.wrapping_add(1);
-        if let Some(el) = a.get(row_temp, col_temp) {
-            match el {
-                &'X' => {
-                    count += 1;
-                    break;
-                }
-                &'L' => break,
-                _ => continue,
-            }
-        }
+    pub fn neighbours(&self, seat: usize) -> &[usize] {
+        &self.neighbours[seat]
+    }
+
+    // Occupancy of every seat, in the same order as the seats
+    pub fn state_of(&self, layout: &Array2D<Cell>) -> Vec<bool> {
+        self.seats
+            .iter()
+            .map(|&pos| layout[pos] == Cell::Occupied)
+            .collect()
+    }
+
+    pub fn to_layout(&self, state: &[bool]) -> Array2D<Cell> {
+        let mut res = Array2D::filled_with(Cell::Floor, self.rows, self.cols);
+        self.seats
+            .iter()
+            .zip(state.iter())
+            .for_each(|(&pos, &occ)| {
+                res[pos] = if occ { Cell::Occupied } else { Cell::Empty };
+            });
+        res
     }
 
-    row_temp = row;
-    col_temp = col;
-
-    while row_temp < a.num_rows() && col_temp < a.num_columns() {
-        row_temp = row_temp.wrapping_add(1);
-        col_temp = col_temp.wrapping_sub(1);
-        if let Some(el) = a.get(row_temp, col_temp) {
-            match el {
-                &'X' => {
-                    count += 1;
-                    break;
+    // One generation; returns the new state and how many seats flipped
+    pub fn step(&self, state: &[bool]) -> (Vec<bool>, usize) {
+        let mut flipped = 0;
+        let next = state
+            .iter()
+            .enumerate()
+            .map(|(i, &occ)| {
+                let count = self.neighbours[i].iter().filter(|&&n| state[n]).count();
+                let new = if occ {
+                    count < self.tolerance
+                } else {
+                    count == 0
+                };
+                if new != occ {
+                    flipped += 1;
                 }
-                &'L' => break,
-                _ => continue,
-            }
-        }
+                new
+            })
+            .collect();
+        (next, flipped)
     }
+}
 
-    match orig {
-        'L' => {
-            if count == 0 {
-                'X'
-            } else {
-                'L'
-            }
+// What one generation looked like once it was computed; generation 0 is the starting layout
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GenerationStats {
+    pub generation: usize,
+    pub occupied: usize,
+    pub flipped: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RunEnd {
+    // The last generation in the history would repeat forever
+    Stable,
+    // The layout went back to the one from generation start, and keeps repeating every period generations
+    Cycle { start: usize, period: usize },
+    // Stopped after the maximum number of generations without settling
+    MaxGenerations,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SeatingRun {
+    pub history: Vec<GenerationStats>,
+    pub end: RunEnd,
+    // The layout of the last generation in the history
+    pub layout: Array2D<Cell>,
+}
+
+impl SeatingRun {
+    pub fn last(&self) -> &GenerationStats {
+        self.history.last().unwrap()
+    }
+}
+
+// Run the rules, keeping every state seen so far to notice when one comes back
+// Stable is just a cycle of period 1, so anything that does not settle can no longer run forever
+pub fn simulate_seating(
+    layout: &Array2D<Cell>,
+    rules: &SeatingRules,
+    max_generations: Option<usize>,
+) -> SeatingRun {
+    let graph = SeatGraph::new(layout, rules);
+    let mut state = graph.state_of(layout);
+    let mut seen: HashMap<Vec<bool>, usize> = HashMap::new();
+    let mut history = vec![GenerationStats {
+        generation: 0,
+        occupied: state.iter().filter(|&&o| o).count(),
+        flipped: 0,
+    }];
+    seen.insert(state.clone(), 0);
+
+    let end = loop {
+        let generation = history.len();
+        // Step before checking the limit, so a layout that settles right at it still counts as stable
+        let (next, flipped) = graph.step(&state);
+        if flipped == 0 {
+            break RunEnd::Stable;
         }
-        'X' => {
-            if count >= 5 {
-                'L'
-            } else {
-                'X'
-            }
+        if let Some(&start) = seen.get(&next) {
+            break RunEnd::Cycle {
+                start,
+                period: generation - start,
+            };
         }
-        _ => orig,
+        if max_generations.is_some_and(|m| generation > m) {
+            break RunEnd::MaxGenerations;
+        }
+        history.push(GenerationStats {
+            generation,
+            occupied: next.iter().filter(|&&o| o).count(),
+            flipped,
+        });
+        seen.insert(next.clone(), generation);
+        state = next;
+    };
+
+    SeatingRun {
+        history,
+        end,
+        layout: graph.to_layout(&state),
     }
 }
 
-#[aoc(day11, part2)]
-pub fn part2(input: &Array2D<char>) -> usize {
-    let mut source = input.clone();
-    let mut dest = Array2D::filled_with('.', input.num_rows(), input.num_columns());
-
-    while source != dest {
-        for row_idx in 0..source.num_rows() {
-            for col_idx in 0..source.num_columns() {
-                dest[(row_idx, col_idx)] = get_part2_value(&source, (row_idx, col_idx));
-            }
-        }
-        swap(&mut source, &mut dest);
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stable {
+    // generations that changed something before the layout settled
+    pub generations: usize,
+    pub occupied: usize,
+    pub layout: Array2D<Cell>,
+}
+
+// Run until nothing changes any more, or complain if the layout never settles
+pub fn stabilise(layout: &Array2D<Cell>, rules: &SeatingRules) -> Result<Stable, Box<dyn Error>> {
+    let run = simulate_seating(layout, rules, None);
+    match run.end {
+        RunEnd::Stable => Ok(Stable {
+            generations: run.last().generation,
+            occupied: run.last().occupied,
+            layout: run.layout,
+        }),
+        RunEnd::Cycle { start, period } => bail!(
+            "seating never settles: cycle of {} generations from generation {}",
+            period,
+            start
+        ),
+        RunEnd::MaxGenerations => bail!("seating did not settle in time"),
     }
-    dest.elements_row_major_iter()
-        .filter(|&&c| c == 'X')
-        .count()
+}
+
+#[aoc(day11, part1)]
+pub fn part1(input: &Array2D<Cell>) -> Result<usize, Box<dyn Error>> {
+    Ok(stabilise(input, &SeatingRules::part1())?.occupied)
+}
+
+#[aoc(day11, part2)]
+pub fn part2(input: &Array2D<Cell>) -> Result<usize, Box<dyn Error>> {
+    Ok(stabilise(input, &SeatingRules::part2())?.occupied)
 }
 
 #[cfg(test)]
@@ -250,16 +371,138 @@ L.LLLLL.LL";
 
     #[test]
     pub fn test_generator() {
-        assert_eq!(true, true);
+        let layout = input_generator(INPUT).unwrap();
+        assert_eq!((layout.num_rows(), layout.num_columns()), (10, 10));
+        assert_eq!(layout[(0, 1)], Cell::Floor);
+        assert_eq!(layout_to_string(&layout), format!("{}\n", INPUT));
+        assert!(input_generator("L.X").is_err());
+        assert!(input_generator("L.L\nLL").is_err());
     }
 
     #[test]
     pub fn test_part1() {
-        assert_eq!(part1(&input_generator(INPUT)), 37);
+        assert_eq!(part1(&input_generator(INPUT).unwrap()).unwrap(), 37);
     }
 
     #[test]
     pub fn test_part2() {
-        assert_eq!(part2(&input_generator(INPUT)), 26);
+        assert_eq!(part2(&input_generator(INPUT).unwrap()).unwrap(), 26);
+    }
+
+    #[test]
+    pub fn test_generations() {
+        let layout = input_generator(INPUT).unwrap();
+        assert_eq!(
+            stabilise(&layout, &SeatingRules::part1())
+                .unwrap()
+                .generations,
+            5
+        );
+        assert_eq!(
+            stabilise(&layout, &SeatingRules::part2())
+                .unwrap()
+                .generations,
+            6
+        );
+    }
+
+    #[test]
+    pub fn test_line_of_sight() {
+        // The example from the puzzle: the empty seat in the middle sees eight occupied ones
+        let layout = input_generator(
+            ".......#.
+...#.....
+.#.......
+.........
+..#L....#
+....#....
+.........
+#........
+...#.....",
+        )
+        .unwrap();
+        let graph = SeatGraph::new(&layout, &SeatingRules::part2());
+        let seat = graph.seats.iter().position(|&p| p == (4, 3)).unwrap();
+        assert_eq!(graph.neighbours(seat).len(), 8);
+
+        let short = SeatingRules {
+            neighbourhood: Neighbourhood::LineOfSight { max_range: Some(2) },
+            ..SeatingRules::part2()
+        };
+        let graph = SeatGraph::new(&layout, &short);
+        assert_eq!(graph.neighbours(seat).len(), 3);
+    }
+
+    #[test]
+    pub fn test_wrap() {
+        let layout = input_generator("L.L\n...\nL.L").unwrap();
+        let wrapping = SeatingRules {
+            wrap: true,
+            ..SeatingRules::part1()
+        };
+        // Each corner touches the other three across the edges
+        let graph = SeatGraph::new(&layout, &wrapping);
+        assert!((0..4).all(|s| graph.neighbours(s).len() == 3));
+        assert!(SeatGraph::new(&layout, &SeatingRules::part1())
+            .neighbours(0)
+            .is_empty());
+
+        // A single seat that can only see itself round the torus never counts itself
+        let graph = SeatGraph::new(
+            &input_generator("L..\n...\n...").unwrap(),
+            &SeatingRules {
+                wrap: true,
+                ..SeatingRules::part2()
+            },
+        );
+        assert!(graph.neighbours(0).is_empty());
+    }
+
+    #[test]
+    pub fn test_history() {
+        let layout = input_generator(INPUT).unwrap();
+        let run = simulate_seating(&layout, &SeatingRules::part1(), None);
+        assert_eq!(run.end, RunEnd::Stable);
+        assert_eq!(
+            run.history
+                .iter()
+                .map(|g| (g.occupied, g.flipped))
+                .collect::<Vec<_>>(),
+            vec![(0, 0), (71, 71), (20, 51), (51, 31), (30, 21), (37, 7)]
+        );
+
+        let run = simulate_seating(&layout, &SeatingRules::part1(), Some(2));
+        assert_eq!(run.end, RunEnd::MaxGenerations);
+        assert_eq!(run.history.len(), 3);
+        assert_eq!(run.last().occupied, 20);
+
+        // Generation 5 is already the final layout, so stopping there is not running out of time
+        let run = simulate_seating(&layout, &SeatingRules::part1(), Some(5));
+        assert_eq!(run.end, RunEnd::Stable);
+        assert_eq!(run.history.len(), 6);
+        let run = simulate_seating(&layout, &SeatingRules::part1(), Some(4));
+        assert_eq!(run.end, RunEnd::MaxGenerations);
+        assert_eq!(run.last().occupied, 30);
+    }
+
+    #[test]
+    pub fn test_cycle() {
+        // With no tolerance at all every seat flips every generation
+        let layout = input_generator("L.L\n.L.").unwrap();
+        let restless = SeatingRules {
+            tolerance: 0,
+            ..SeatingRules::part1()
+        };
+        let run = simulate_seating(&layout, &restless, None);
+        assert_eq!(
+            run.end,
+            RunEnd::Cycle {
+                start: 0,
+                period: 2
+            }
+        );
+        assert_eq!(run.history.len(), 2);
+        assert_eq!(layout_to_string(&run.layout), "#.#\n.#.\n");
+        assert!(stabilise(&layout, &restless).is_err());
     }
 }
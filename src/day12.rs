@@ -1,119 +1,401 @@
-use std::mem::swap;
-
 use aoc_runner_derive::{aoc, aoc_generator};
+use lazy_static::lazy_static;
 use regex::Regex;
+use simple_error::*;
+use std::error::Error;
+use std::str::FromStr;
+
+lazy_static! {
+    static ref COMMAND: Regex = Regex::new(r"^([NSEWLRF])(\d+)$").unwrap();
+}
+
+// Turns are kept as a number of quarter turns, 0 to 3, so rotating never leaves the integers
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NavCommand {
+    North(i32),
+    South(i32),
+    East(i32),
+    West(i32),
+    Left(u8),
+    Right(u8),
+    Forward(i32),
+}
+
+impl FromStr for NavCommand {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let cap = match COMMAND.captures(s) {
+            Some(cap) => cap,
+            None => bail!("bad navigation command: {}", s),
+        };
+        let value = cap.get(2).unwrap().as_str().parse::<i32>()?;
+        let quarters = || -> Result<u8, Box<dyn Error>> {
+            if value % 90 != 0 {
+                bail!("only right-angle turns are supported: {}", s);
+            }
+            Ok(((value / 90) % 4) as u8)
+        };
+        Ok(match cap.get(1).unwrap().as_str() {
+            "N" => NavCommand::North(value),
+            "S" => NavCommand::South(value),
+            "E" => NavCommand::East(value),
+            "W" => NavCommand::West(value),
+            "L" => NavCommand::Left(quarters()?),
+            "R" => NavCommand::Right(quarters()?),
+            "F" => NavCommand::Forward(value),
+            _ => unreachable!(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Heading {
+    North,
+    East,
+    South,
+    West,
+}
+
+impl Heading {
+    const CLOCKWISE: [Heading; 4] = [Heading::North, Heading::East, Heading::South, Heading::West];
+
+    pub fn right(self, quarters: u8) -> Heading {
+        let idx = Heading::CLOCKWISE.iter().position(|&h| h == self).unwrap();
+        Heading::CLOCKWISE[(idx + quarters as usize) % 4]
+    }
+
+    pub fn left(self, quarters: u8) -> Heading {
+        self.right((4 - quarters % 4) % 4)
+    }
+
+    // One step in this direction, east and north being positive
+    pub fn unit(self) -> (i32, i32) {
+        match self {
+            Heading::North => (0, 1),
+            Heading::East => (1, 0),
+            Heading::South => (0, -1),
+            Heading::West => (-1, 0),
+        }
+    }
+}
+
+// Rotate a vector by quarter turns, counterclockwise
+fn rotate_left((x, y): (i32, i32), quarters: u8) -> (i32, i32) {
+    (0..quarters % 4).fold((x, y), |(x, y), _| (-y, x))
+}
+
+pub trait Navigator {
+    fn apply(&mut self, cmd: &NavCommand);
+
+    fn position(&self) -> (i32, i32);
+
+    // Where the waypoint is on the map, for navigators that have one
+    fn waypoint_position(&self) -> Option<(i32, i32)> {
+        None
+    }
 
-#[derive(Debug)]
+    fn manhattan(&self) -> i32 {
+        let (x, y) = self.position();
+        x.abs() + y.abs()
+    }
+
+    fn navigate(&mut self, cmds: &[NavCommand]) {
+        cmds.iter().for_each(|cmd| self.apply(cmd));
+    }
+}
+
+// Part 1: the commands move the ship itself
+#[derive(Debug, Clone, PartialEq)]
 pub struct Vessel {
     x: i32,
     y: i32,
-    dir: i32,
+    heading: Heading,
 }
 
-#[aoc_generator(day12)]
-pub fn input_generator(input: &str) -> Vec<String> {
-    input
-        .lines()
-        .map(|l| String::from(l))
-        .collect::<Vec<String>>()
+impl Default for Vessel {
+    fn default() -> Vessel {
+        Vessel {
+            x: 0,
+            y: 0,
+            heading: Heading::East,
+        }
+    }
 }
 
-#[aoc(day12, part1)]
-pub fn part1(input: &Vec<String>) -> i32 {
-    let mut ves = Vessel { x: 0, y: 0, dir: 0 };
-
-    let matcher = Regex::new(r"^([NSEWLRF]{1})(\d+)$").unwrap();
-
-    input.iter().for_each(|s| {
-        let caps = matcher.captures(s);
-        if let Some(cap) = caps {
-            let command = cap.get(1).unwrap().as_str();
-            let value = cap.get(2).unwrap().as_str().parse::<i32>().unwrap();
-            match command {
-                "N" => {
-                    ves.y += value;
-                }
-                "S" => {
-                    ves.y -= value;
-                }
-                "E" => {
-                    ves.x += value;
-                }
-                "W" => {
-                    ves.x -= value;
-                }
-                "L" => {
-                    ves.dir += value;
-                }
-                "R" => {
-                    ves.dir -= value;
-                    if ves.dir < 0 {
-                        ves.dir = 360 + ves.dir;
-                    }
-                }
-                "F" => {
-                    ves.x += ((value as f64) * (ves.dir as f64).to_radians().cos()) as i32;
-                    ves.y += ((value as f64) * (ves.dir as f64).to_radians().sin()) as i32;
-                }
-                _ => panic!("Isn't supposed to happen."),
+impl Vessel {
+    pub fn heading(&self) -> Heading {
+        self.heading
+    }
+}
+
+impl Navigator for Vessel {
+    fn apply(&mut self, cmd: &NavCommand) {
+        match *cmd {
+            NavCommand::North(v) => self.y += v,
+            NavCommand::South(v) => self.y -= v,
+            NavCommand::East(v) => self.x += v,
+            NavCommand::West(v) => self.x -= v,
+            NavCommand::Left(q) => self.heading = self.heading.left(q),
+            NavCommand::Right(q) => self.heading = self.heading.right(q),
+            NavCommand::Forward(v) => {
+                let (dx, dy) = self.heading.unit();
+                self.x += v * dx;
+                self.y += v * dy;
             }
         }
-    });
+    }
 
-    ves.x.abs() + ves.y.abs()
+    fn position(&self) -> (i32, i32) {
+        (self.x, self.y)
+    }
 }
 
-#[aoc(day12, part2)]
-pub fn part2(input: &Vec<String>) -> i32 {
-    let mut ves = Vessel { x: 0, y: 0, dir: 0 };
-    let mut wp = Vessel {
-        x: 10,
-        y: 1,
-        dir: 0,
-    };
-
-    let matcher = Regex::new(r"^([NSEWLRF]{1})(\d+)$").unwrap();
-
-    input.iter().for_each(|s| {
-        let caps = matcher.captures(s);
-        if let Some(cap) = caps {
-            let command = cap.get(1).unwrap().as_str();
-            let value = cap.get(2).unwrap().as_str().parse::<i32>().unwrap();
-            match command {
-                "N" => {
-                    wp.y += value;
-                }
-                "S" => {
-                    wp.y -= value;
-                }
-                "E" => {
-                    wp.x += value;
-                }
-                "W" => {
-                    wp.x -= value;
-                }
-                "L" => {
-                    for _ in 0..value / 90 {
-                        swap(&mut wp.x, &mut wp.y);
-                        wp.x = -wp.x;
-                    }
-                }
-                "R" => {
-                    for _ in 0..value / 90 {
-                        swap(&mut wp.x, &mut wp.y);
-                        wp.y = -wp.y;
-                    }
-                }
-                "F" => {
-                    ves.x += value * wp.x;
-                    ves.y += value * wp.y;
-                }
-                _ => panic!("Isn't supposed to happen."),
+// Part 2: the commands move a waypoint relative to the ship, and the ship only moves towards it
+#[derive(Debug, Clone, PartialEq)]
+pub struct WaypointVessel {
+    x: i32,
+    y: i32,
+    wx: i32,
+    wy: i32,
+}
+
+impl Default for WaypointVessel {
+    fn default() -> WaypointVessel {
+        WaypointVessel {
+            x: 0,
+            y: 0,
+            wx: 10,
+            wy: 1,
+        }
+    }
+}
+
+impl WaypointVessel {
+    // Relative to the ship
+    pub fn waypoint(&self) -> (i32, i32) {
+        (self.wx, self.wy)
+    }
+}
+
+impl Navigator for WaypointVessel {
+    fn apply(&mut self, cmd: &NavCommand) {
+        match *cmd {
+            NavCommand::North(v) => self.wy += v,
+            NavCommand::South(v) => self.wy -= v,
+            NavCommand::East(v) => self.wx += v,
+            NavCommand::West(v) => self.wx -= v,
+            NavCommand::Left(q) => {
+                let (wx, wy) = rotate_left((self.wx, self.wy), q);
+                self.wx = wx;
+                self.wy = wy;
+            }
+            NavCommand::Right(q) => {
+                let (wx, wy) = rotate_left((self.wx, self.wy), (4 - q % 4) % 4);
+                self.wx = wx;
+                self.wy = wy;
+            }
+            NavCommand::Forward(v) => {
+                self.x += v * self.wx;
+                self.y += v * self.wy;
             }
         }
-    });
+    }
+
+    fn position(&self) -> (i32, i32) {
+        (self.x, self.y)
+    }
+
+    fn waypoint_position(&self) -> Option<(i32, i32)> {
+        Some((self.x + self.wx, self.y + self.wy))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingBox {
+    pub min_x: i32,
+    pub min_y: i32,
+    pub max_x: i32,
+    pub max_y: i32,
+}
+
+impl BoundingBox {
+    fn of(points: &[(i32, i32)]) -> Option<BoundingBox> {
+        let (&(x0, y0), rest) = points.split_first()?;
+        Some(rest.iter().fold(
+            BoundingBox {
+                min_x: x0,
+                min_y: y0,
+                max_x: x0,
+                max_y: y0,
+            },
+            |b, &(x, y)| BoundingBox {
+                min_x: b.min_x.min(x),
+                min_y: b.min_y.min(y),
+                max_x: b.max_x.max(x),
+                max_y: b.max_y.max(y),
+            },
+        ))
+    }
+
+    fn union(&self, other: &BoundingBox) -> BoundingBox {
+        BoundingBox {
+            min_x: self.min_x.min(other.min_x),
+            min_y: self.min_y.min(other.min_y),
+            max_x: self.max_x.max(other.max_x),
+            max_y: self.max_y.max(other.max_y),
+        }
+    }
+}
+
+// Positions before the first command and after every command, so step i follows command i
+// The waypoint path stays empty for navigators without a waypoint
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Trajectory {
+    pub ship: Vec<(i32, i32)>,
+    pub waypoint: Vec<(i32, i32)>,
+}
+
+impl Trajectory {
+    pub fn ship_bounds(&self) -> Option<BoundingBox> {
+        BoundingBox::of(&self.ship)
+    }
+
+    pub fn waypoint_bounds(&self) -> Option<BoundingBox> {
+        BoundingBox::of(&self.waypoint)
+    }
+
+    // Manhattan length of the whole route, the same metric the puzzle uses
+    pub fn distance(&self) -> i64 {
+        self.ship
+            .windows(2)
+            .map(|w| ((w[1].0 - w[0].0).abs() + (w[1].1 - w[0].1).abs()) as i64)
+            .sum()
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut res = String::from("step,ship_x,ship_y,waypoint_x,waypoint_y\n");
+        self.ship.iter().enumerate().for_each(|(i, (x, y))| {
+            let wp = match self.waypoint.get(i) {
+                Some((wx, wy)) => format!("{},{}", wx, wy),
+                None => String::from(","),
+            };
+            res.push_str(&format!("{},{},{},{}\n", i, x, y, wp));
+        });
+        res
+    }
+
+    // The ship in blue and the waypoint in orange; SVG y grows downwards, so north is flipped up
+    pub fn to_svg(&self) -> String {
+        let bounds = match (self.ship_bounds(), self.waypoint_bounds()) {
+            (Some(s), Some(w)) => s.union(&w),
+            (Some(s), None) => s,
+            _ => BoundingBox::of(&[(0, 0)]).unwrap(),
+        };
+        let width = (bounds.max_x - bounds.min_x).max(1);
+        let height = (bounds.max_y - bounds.min_y).max(1);
+        let margin = width.max(height) / 20 + 1;
+        let stroke = width.max(height) / 200 + 1;
+        let polyline = |points: &[(i32, i32)], colour: &str| {
+            format!(
+                "  <polyline fill=\"none\" stroke=\"{}\" stroke-width=\"{}\" points=\"{}\"/>\n",
+                colour,
+                stroke,
+                points
+                    .iter()
+                    .map(|(x, y)| format!("{},{}", x, -y))
+                    .collect::<Vec<String>>()
+                    .join(" ")
+            )
+        };
+
+        let mut res = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"{} {} {} {}\">\n",
+            bounds.min_x - margin,
+            -bounds.max_y - margin,
+            width + 2 * margin,
+            height + 2 * margin
+        );
+        if !self.waypoint.is_empty() {
+            res.push_str(&polyline(&self.waypoint, "orange"));
+        }
+        res.push_str(&polyline(&self.ship, "blue"));
+        res.push_str("</svg>\n");
+        res
+    }
+
+    pub fn save_csv(&self, path: &str) -> std::io::Result<()> {
+        std::fs::write(path, self.to_csv())
+    }
+
+    pub fn save_svg(&self, path: &str) -> std::io::Result<()> {
+        std::fs::write(path, self.to_svg())
+    }
+}
+
+// Wraps any navigator and records where it has been
+pub struct Recorder<N: Navigator> {
+    nav: N,
+    trajectory: Trajectory,
+}
+
+impl<N: Navigator> Recorder<N> {
+    pub fn new(nav: N) -> Recorder<N> {
+        let mut trajectory = Trajectory::default();
+        trajectory.ship.push(nav.position());
+        if let Some(wp) = nav.waypoint_position() {
+            trajectory.waypoint.push(wp);
+        }
+        Recorder { nav, trajectory }
+    }
+
+    pub fn navigator(&self) -> &N {
+        &self.nav
+    }
+
+    pub fn trajectory(&self) -> &Trajectory {
+        &self.trajectory
+    }
+}
+
+impl<N: Navigator> Navigator for Recorder<N> {
+    fn apply(&mut self, cmd: &NavCommand) {
+        self.nav.apply(cmd);
+        self.trajectory.ship.push(self.nav.position());
+        if let Some(wp) = self.nav.waypoint_position() {
+            self.trajectory.waypoint.push(wp);
+        }
+    }
+
+    fn position(&self) -> (i32, i32) {
+        self.nav.position()
+    }
 
-    ves.x.abs() + ves.y.abs()
+    fn waypoint_position(&self) -> Option<(i32, i32)> {
+        self.nav.waypoint_position()
+    }
+}
+
+#[aoc_generator(day12)]
+pub fn input_generator(input: &str) -> Result<Vec<NavCommand>, Box<dyn Error>> {
+    input
+        .lines()
+        .map(|l| l.parse::<NavCommand>())
+        .collect::<Result<Vec<NavCommand>, Box<dyn Error>>>()
+}
+
+#[aoc(day12, part1)]
+pub fn part1(input: &[NavCommand]) -> i32 {
+    let mut ves = Vessel::default();
+    ves.navigate(input);
+    ves.manhattan()
+}
+
+#[aoc(day12, part2)]
+pub fn part2(input: &[NavCommand]) -> i32 {
+    let mut ves = WaypointVessel::default();
+    ves.navigate(input);
+    ves.manhattan()
 }
 
 #[cfg(test)]
@@ -129,24 +411,108 @@ F11";
     #[test]
     pub fn test_generator() {
         assert_eq!(
-            input_generator(INPUT),
+            input_generator(INPUT).unwrap(),
             vec![
-                String::from("F10"),
-                String::from("N3"),
-                String::from("F7"),
-                String::from("R90"),
-                String::from("F11")
+                NavCommand::Forward(10),
+                NavCommand::North(3),
+                NavCommand::Forward(7),
+                NavCommand::Right(1),
+                NavCommand::Forward(11)
             ]
         );
+        assert_eq!(
+            input_generator("L270\nR360").unwrap(),
+            vec![NavCommand::Left(3), NavCommand::Right(0)]
+        );
+        assert!(input_generator("R45").is_err());
+        assert!(input_generator("X10").is_err());
+    }
+
+    #[test]
+    pub fn test_heading() {
+        assert_eq!(Heading::East.left(1), Heading::North);
+        assert_eq!(Heading::East.right(3), Heading::North);
+        assert_eq!(Heading::West.right(2), Heading::East);
+        assert_eq!(Heading::South.left(0), Heading::South);
+        assert_eq!(rotate_left((10, 4), 3), (4, -10));
+    }
+
+    #[test]
+    pub fn test_navigators() {
+        let cmds = input_generator(INPUT).unwrap();
+        let mut ves = Vessel::default();
+        ves.navigate(&cmds);
+        assert_eq!((ves.position(), ves.heading()), ((17, -8), Heading::South));
+
+        let mut ves = WaypointVessel::default();
+        ves.navigate(&cmds);
+        assert_eq!((ves.position(), ves.waypoint()), ((214, -72), (4, -10)));
     }
 
     #[test]
     pub fn test_part1() {
-        assert_eq!(part1(&input_generator(INPUT)), 25);
+        assert_eq!(part1(&input_generator(INPUT).unwrap()), 25);
     }
 
     #[test]
     pub fn test_part2() {
-        assert_eq!(part2(&input_generator(INPUT)), 286);
+        assert_eq!(part2(&input_generator(INPUT).unwrap()), 286);
+    }
+
+    #[test]
+    pub fn test_trajectory() {
+        let cmds = input_generator(INPUT).unwrap();
+        let mut rec = Recorder::new(Vessel::default());
+        rec.navigate(&cmds);
+        let t = rec.trajectory();
+        assert_eq!(
+            t.ship,
+            vec![(0, 0), (10, 0), (10, 3), (17, 3), (17, 3), (17, -8)]
+        );
+        assert!(t.waypoint.is_empty());
+        assert_eq!(t.distance(), 31);
+        assert_eq!(
+            t.ship_bounds(),
+            Some(BoundingBox {
+                min_x: 0,
+                min_y: -8,
+                max_x: 17,
+                max_y: 3
+            })
+        );
+        assert_eq!(rec.manhattan(), 25);
+
+        let mut rec = Recorder::new(WaypointVessel::default());
+        rec.navigate(&cmds);
+        let t = rec.trajectory();
+        assert_eq!(t.ship[1], (100, 10));
+        assert_eq!(t.waypoint[..3], [(10, 1), (110, 11), (110, 14)]);
+        assert_eq!(t.waypoint_bounds().unwrap().max_x, 218);
+        assert_eq!(t.distance(), 110 + 98 + 154);
+    }
+
+    #[test]
+    pub fn test_export() {
+        let cmds = input_generator(INPUT).unwrap();
+        let mut rec = Recorder::new(WaypointVessel::default());
+        rec.navigate(&cmds);
+        let csv = rec.trajectory().to_csv();
+        let mut lines = csv.lines();
+        assert_eq!(
+            lines.next(),
+            Some("step,ship_x,ship_y,waypoint_x,waypoint_y")
+        );
+        assert_eq!(lines.next(), Some("0,0,0,10,1"));
+        assert_eq!(csv.lines().count(), 7);
+
+        let svg = rec.trajectory().to_svg();
+        assert!(svg.starts_with("<svg "));
+        assert_eq!(svg.matches("<polyline ").count(), 2);
+        assert!(svg.contains("points=\"0,0 100,-10 100,-10 170,-38 170,-38 214,72\""));
+
+        let mut rec = Recorder::new(Vessel::default());
+        rec.navigate(&cmds);
+        assert!(rec.trajectory().to_csv().contains("\n1,10,0,,\n"));
+        assert_eq!(rec.trajectory().to_svg().matches("<polyline ").count(), 1);
     }
 }
@@ -1,59 +1,138 @@
+use crate::crt::{self, CrtError, Solution};
 use aoc_runner_derive::{aoc, aoc_generator};
+use simple_error::*;
+use std::error::Error;
 
 // TEMPLATE
 
+// Earliest timestamp, and the bus list with None for every x
+pub type Notes = (u32, Vec<Option<u32>>);
+
 #[aoc_generator(day13)]
-pub fn input_generator(input: &str) -> (u32, Vec<Option<u32>>) {
+pub fn input_generator(input: &str) -> Result<Notes, Box<dyn Error>> {
     let mut li = input.lines();
-    let tts = li.next().unwrap().parse::<u32>().unwrap();
+    let tts = li
+        .next()
+        .ok_or("missing earliest timestamp")?
+        .parse::<u32>()?;
     let vb = li
         .next()
-        .unwrap()
+        .ok_or("missing bus list")?
         .split(',')
         .map(|s| s.parse::<u32>().ok())
         .collect::<Vec<Option<u32>>>();
-    (tts, vb)
+    Timetable::new(&vb)?;
+    Ok((tts, vb))
+}
+
+// The bus schedule: position in the list is the bus' offset, None stands for an x
+#[derive(Debug, Clone, PartialEq)]
+pub struct Timetable {
+    slots: Vec<Option<u64>>,
+}
+
+impl Timetable {
+    // A bus leaving every 0 minutes never leaves, and would divide by zero everywhere below
+    pub fn new(vb: &[Option<u32>]) -> Result<Timetable, Box<dyn Error>> {
+        if let Some(i) = vb.iter().position(|&el| el == Some(0)) {
+            bail!("bus at position {} has ID 0", i);
+        }
+        Ok(Timetable {
+            slots: vb.iter().map(|el| el.map(u64::from)).collect(),
+        })
+    }
+
+    pub fn buses(&self) -> impl Iterator<Item = u64> + '_ {
+        self.slots.iter().filter_map(|&el| el)
+    }
+
+    // First departure of the bus at or after t, in plain integers
+    pub fn next_departure(bus: u64, t: u64) -> u64 {
+        t.div_ceil(bus) * bus
+    }
+
+    // The earliest (time, bus) anyone arriving at t can catch
+    pub fn earliest_after(&self, t: u64) -> Option<(u64, u64)> {
+        self.buses()
+            .map(|bus| (Self::next_departure(bus, t), bus))
+            .min()
+    }
+
+    // Every (time, bus) departure with from <= time <= to, in time order
+    pub fn departures(&self, from: u64, to: u64) -> Vec<(u64, u64)> {
+        let mut res = self
+            .buses()
+            .flat_map(|bus| {
+                (Self::next_departure(bus, from)..=to)
+                    .step_by(bus as usize)
+                    .map(move |t| (t, bus))
+            })
+            .collect::<Vec<(u64, u64)>>();
+        res.sort_unstable();
+        res
+    }
+
+    // Earliest t at which each chosen bus leaves at t + its offset, and how often that repeats
+    pub fn alignment(&self, subset: &[u64]) -> Result<Solution, Box<dyn Error>> {
+        let congruences = subset
+            .iter()
+            .map(
+                |&bus| match self.slots.iter().position(|&el| el == Some(bus)) {
+                    Some(i) => Ok((-(i as i128), bus as i128)),
+                    None => bail!("bus {} is not in the timetable", bus),
+                },
+            )
+            .collect::<Result<Vec<(i128, i128)>, SimpleError>>()?;
+        Ok(crt::solve(&congruences)?)
+    }
+
+    // The departure grid from the puzzle statement, one row per minute, D where a bus leaves
+    pub fn grid(&self, from: u64, to: u64) -> String {
+        let mut res = format!("{:<9}", "time");
+        self.buses()
+            .for_each(|bus| res.push_str(&format!("{:<8}", format!("bus {}", bus))));
+        res.truncate(res.trim_end().len());
+        for t in from..=to {
+            let mut row = format!("\n{:<9}", t);
+            self.buses().for_each(|bus| {
+                let c = if t % bus == 0 { 'D' } else { '.' };
+                row.push_str(&format!("  {}     ", c));
+            });
+            res.push_str(row.trim_end());
+        }
+        res
+    }
 }
 
 #[aoc(day13, part1)]
-pub fn part1((tts, vb): &(u32, Vec<Option<u32>>)) -> u32 {
-    let result = vb
-        .iter()
-        .filter(|&el| el.is_some())
-        .map(|&el| el.unwrap())
-        .map(|el| (el, (((tts / el) as f32).ceil() + 1.0) as u32 * el - *tts))
-        .min_by(|(_, diff1), (_, diff2)| diff1.cmp(diff2))
-        .unwrap();
-    result.0 * result.1
+pub fn part1((tts, vb): &Notes) -> Result<u64, Box<dyn Error>> {
+    let tts = u64::from(*tts);
+    match Timetable::new(vb)?.earliest_after(tts) {
+        Some((time, bus)) => Ok(bus * (time - tts)),
+        None => bail!("no buses in the timetable"),
+    }
 }
 
-#[aoc(day13, part2)]
-pub fn part2((_, vb): &(u32, Vec<Option<u32>>)) -> isize {
-    let buses = vb
-        .iter()
+// Every bus with its offset in the list, as t = -offset (mod bus)
+pub fn congruences(vb: &[Option<u32>]) -> Vec<(i128, i128)> {
+    vb.iter()
         .enumerate()
-        .map(|(i, &el)| (el, i))
-        .filter(|&(el, _)| el.is_some())
-        .map(|(el, i)| (el.unwrap() as isize, i as isize))
-        .collect::<Vec<(isize, isize)>>();
-
-    // buses are prime numbers!
-    // our task is formulated so that the diff in time is the remainder in the modulus division
-    // i.e., t + bus_diff = bus * k, hence t = -bus_diff mod bus
-    // So for the example, t = 0 mod 7, t = -1 mod 13, t = -4 mod 59, etc, etc.
-    // As we have the remainders of dividing t by several integers, we can determine t modulus
-    // the product of all bus numbers - hence, the result we're looking for -
-    // using Chinese remainder theorem: https://en.wikipedia.org/wiki/Chinese_remainder_theorem
-
-    let prod = buses.iter().map(|&(bus, _)| bus).product();
-
-    buses
-        .iter()
-        .map(|(bus, diff)| {
-            -diff * (prod / bus) * (0..bus - 2).fold(1, |acc, _| (acc * prod / bus) % bus)
-        })
-        .sum::<isize>()
-        .rem_euclid(prod)
+        .filter_map(|(i, el)| el.map(|bus| (-(i as i128), bus as i128)))
+        .collect()
+}
+
+// our task is formulated so that the diff in time is the remainder in the modulus division
+// i.e., t + bus_diff = bus * k, hence t = -bus_diff mod bus
+// So for the example, t = 0 mod 7, t = -1 mod 13, t = -4 mod 59, etc, etc.
+// The bus numbers don't have to be prime or even coprime: the general CRT either finds the
+// earliest t and the period after which the pattern repeats, or tells there is none
+pub fn earliest_alignment(vb: &[Option<u32>]) -> Result<Solution, CrtError> {
+    crt::solve(&congruences(vb))
+}
+
+#[aoc(day13, part2)]
+pub fn part2((_, vb): &Notes) -> Result<i128, CrtError> {
+    earliest_alignment(vb).map(|s| s.value)
 }
 
 #[cfg(test)]
@@ -66,7 +145,7 @@ mod tests {
     #[test]
     pub fn test_generator() {
         assert_eq!(
-            input_generator(INPUT),
+            input_generator(INPUT).unwrap(),
             (
                 939,
                 vec![
@@ -81,20 +160,89 @@ mod tests {
                 ]
             )
         );
+        assert_eq!(
+            input_generator("").unwrap_err().to_string(),
+            "missing earliest timestamp"
+        );
+        assert_eq!(
+            input_generator("939").unwrap_err().to_string(),
+            "missing bus list"
+        );
     }
 
     #[test]
     pub fn test_part1() {
-        assert_eq!(part1(&input_generator(INPUT)), 295);
+        assert_eq!(part1(&input_generator(INPUT).unwrap()).unwrap(), 295);
     }
 
     #[test]
     pub fn test_part2() {
-        assert_eq!(part2(&input_generator(INPUT)), 1068781);
+        assert_eq!(part2(&input_generator(INPUT).unwrap()), Ok(1068781));
     }
 
     #[test]
     pub fn test_part2_simple() {
-        assert_eq!(part2(&input_generator(INPUT1)), 3417);
+        assert_eq!(part2(&input_generator(INPUT1).unwrap()), Ok(3417));
+    }
+
+    #[test]
+    pub fn test_non_prime_buses() {
+        // 4 and 6 share a factor: t = 0 (mod 4), t = -1 (mod 6) can't both hold
+        assert_eq!(
+            part2(&input_generator("0\n4,6").unwrap()),
+            Err(CrtError::NoSolution)
+        );
+        // t = 0 (mod 4), t = -2 (mod 6) first holds at 4, and again every 12
+        assert_eq!(
+            earliest_alignment(&input_generator("0\n4,x,6").unwrap().1),
+            Ok(Solution {
+                value: 4,
+                period: 12
+            })
+        );
+    }
+
+    #[test]
+    pub fn test_zero_bus() {
+        assert_eq!(
+            input_generator("10\n0,7").unwrap_err().to_string(),
+            "bus at position 0 has ID 0"
+        );
+        assert!(Timetable::new(&[Some(7), None, Some(0)]).is_err());
+        assert!(part1(&(10, vec![Some(0)])).is_err());
+        assert!(part1(&(10, vec![None])).is_err());
+    }
+
+    #[test]
+    pub fn test_timetable() {
+        let tt = Timetable::new(&input_generator(INPUT).unwrap().1).unwrap();
+        assert_eq!(tt.earliest_after(939), Some((944, 59)));
+        assert_eq!(tt.earliest_after(945), Some((945, 7)));
+        assert_eq!(tt.departures(939, 945), vec![(944, 59), (945, 7)]);
+        assert_eq!(tt.departures(1, 7), vec![(7, 7)]);
+        // No float rounding at timestamps far beyond what an f32 holds exactly
+        assert_eq!(
+            Timetable::new(&[Some(7)])
+                .unwrap()
+                .earliest_after(100_000_000_000_001),
+            Some((100_000_000_000_005, 7))
+        );
+
+        assert_eq!(
+            tt.alignment(&[7, 13]).unwrap(),
+            Solution {
+                value: 77,
+                period: 91
+            }
+        );
+        assert_eq!(tt.alignment(&[7, 13, 59, 31, 19]).unwrap().value, 1068781);
+        assert!(tt.alignment(&[7, 17]).is_err());
+
+        assert_eq!(
+            tt.grid(944, 945),
+            "time     bus 7   bus 13  bus 59  bus 31  bus 19
+944        .       .       D       .       .
+945        D       .       .       .       ."
+        );
     }
 }
@@ -1,90 +1,361 @@
 use aoc_runner_derive::{aoc, aoc_generator};
+use lazy_static::lazy_static;
 use regex::Regex;
+use simple_error::*;
 use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+lazy_static! {
+    static ref MASK: Regex = Regex::new(r"^mask = (\S*)$").unwrap();
+    static ref WRITE: Regex = Regex::new(r"^mem\[(\d+)\] = (\d+)$").unwrap();
+}
+
+pub const BITS: u32 = 36;
+pub const WORD: u64 = (1 << BITS) - 1;
+
+// A 36 character mask, kept as two bit sets: the 1s and the Xs. Everything else is a 0
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Mask {
+    pub ones: u64,
+    pub floating: u64,
+}
+
+impl Mask {
+    pub fn zeros(&self) -> u64 {
+        WORD & !self.ones & !self.floating
+    }
+
+    // Version 1: 0s and 1s overwrite the value, Xs leave it alone
+    pub fn apply_value(&self, value: u64) -> u64 {
+        (value & self.floating) | self.ones
+    }
+
+    // Version 2: 1s set the address bit, 0s leave it alone, Xs take every combination.
+    // Walks the subsets of the floating bits in increasing order
+    pub fn floating_addresses(&self, addr: u64) -> impl Iterator<Item = u64> {
+        let base = (addr | self.ones) & !self.floating;
+        let floating = self.floating;
+        let mut next = Some(0u64);
+        std::iter::from_fn(move || {
+            let sub = next?;
+            next = if sub == floating {
+                None
+            } else {
+                Some(sub.wrapping_sub(floating) & floating)
+            };
+            Some(base | sub)
+        })
+    }
+}
+
+impl FromStr for Mask {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() != BITS as usize {
+            bail!("mask must have {} bits, got {}: {}", BITS, s.len(), s);
+        }
+        s.chars().try_fold(
+            Mask {
+                ones: 0,
+                floating: 0,
+            },
+            |m, c| {
+                let (one, x) = match c {
+                    '0' => (0, 0),
+                    '1' => (1, 0),
+                    'X' => (0, 1),
+                    _ => bail!("unexpected character in mask: {}", c),
+                };
+                Ok(Mask {
+                    ones: m.ones << 1 | one,
+                    floating: m.floating << 1 | x,
+                })
+            },
+        )
+    }
+}
+
+impl fmt::Display for Mask {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        (0..BITS).rev().try_for_each(|b| {
+            let c = if self.ones >> b & 1 == 1 {
+                '1'
+            } else if self.floating >> b & 1 == 1 {
+                'X'
+            } else {
+                '0'
+            };
+            write!(f, "{}", c)
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DockingInstruction {
+    SetMask(Mask),
+    Write { addr: u64, value: u64 },
+}
+
+impl FromStr for DockingInstruction {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(cap) = MASK.captures(s) {
+            return Ok(DockingInstruction::SetMask(cap[1].parse::<Mask>()?));
+        }
+        let cap = match WRITE.captures(s) {
+            Some(cap) => cap,
+            None => bail!("unknown instruction: {}", s),
+        };
+        let addr = cap[1].parse::<u64>()?;
+        let value = cap[2].parse::<u64>()?;
+        if addr > WORD || value > WORD {
+            bail!("address and value must fit in {} bits: {}", BITS, s);
+        }
+        Ok(DockingInstruction::Write { addr, value })
+    }
+}
 
 #[aoc_generator(day14)]
-pub fn input_generator(input: &str) -> Vec<String> {
+pub fn input_generator(input: &str) -> Result<Vec<DockingInstruction>, Box<dyn Error>> {
     input
         .lines()
-        .map(|s| String::from(s))
-        .collect::<Vec<String>>()
+        .map(|l| l.parse::<DockingInstruction>())
+        .collect::<Result<Vec<DockingInstruction>, Box<dyn Error>>>()
 }
 
-#[aoc(day14, part1)]
-pub fn part1(input: &Vec<String>) -> usize {
-    let mut mem: HashMap<usize, usize> = HashMap::new();
-    // mask.0 will be the mask to extract unchanged bits
-    // mask.1 will be the bits to add
-    let mut mask: (usize, usize) = (0, 0);
-    let mask_regex = Regex::new(r"^mask = ([01X]{36})$").unwrap();
-    let mem_regex = Regex::new(r"^mem\[(\d+)\] = (\d+)$").unwrap();
-    input.iter().for_each(|l| {
-        // 1. Parse the line
-        // 2. Either update mask
-        // 3. Or update hashmap with address/value pair after applying mask
-        if let Some(caps) = mask_regex.captures(l) {
-            let digits = caps.get(1).unwrap().as_str();
-            mask.0 = usize::from_str_radix(digits.replace('1', "0").replace('X', "1").as_str(), 2)
-                .unwrap();
-            mask.1 = usize::from_str_radix(digits.replace('X', "0").as_str(), 2).unwrap();
-        } else if let Some(caps) = mem_regex.captures(l) {
-            let addr = caps.get(1).unwrap().as_str().parse::<usize>().unwrap();
-            let val = caps.get(2).unwrap().as_str().parse::<usize>().unwrap();
-            let val = (val & mask.0) + mask.1;
-            mem.insert(addr, val);
+// Turns a single write under the current mask into the (address, value) pairs that land in memory
+pub trait Decoder {
+    // The mask in force before the program sets one: it must leave writes untouched
+    fn initial_mask(&self) -> Mask;
+    fn decode(&self, mask: &Mask, addr: u64, value: u64) -> Vec<(u64, u64)>;
+}
+
+pub struct V1;
+
+impl Decoder for V1 {
+    fn initial_mask(&self) -> Mask {
+        Mask {
+            ones: 0,
+            floating: WORD,
         }
-    });
-    mem.values().sum()
+    }
+
+    fn decode(&self, mask: &Mask, addr: u64, value: u64) -> Vec<(u64, u64)> {
+        vec![(addr, mask.apply_value(value))]
+    }
 }
 
-#[aoc(day14, part2)]
-pub fn part2(input: &Vec<String>) -> usize {
-    let mut mem: HashMap<usize, usize> = HashMap::new();
-    let mut mask: (usize, usize) = (0, 0);
-    let mask_regex = Regex::new(r"^mask = ([01X]{36})$").unwrap();
-    let mem_regex = Regex::new(r"^mem\[(\d+)\] = (\d+)$").unwrap();
-    input.iter().for_each(|l| {
-        if let Some(caps) = mask_regex.captures(l) {
-            let extracted_mask = String::from(caps.get(1).unwrap().as_str());
-            mask = (
-                usize::from_str_radix(extracted_mask.replace('X', "0").as_str(), 2).unwrap(),
-                usize::from_str_radix(extracted_mask.replace('X', "1").as_str(), 2).unwrap(),
-            );
-        } else if let Some(caps) = mem_regex.captures(l) {
-            let addr = caps.get(1).unwrap().as_str().parse::<usize>().unwrap();
-            let val = caps.get(2).unwrap().as_str().parse::<usize>().unwrap();
-            let bitdiff = mask.0 ^ mask.1;
-            // we will need to write to 2^(Xes in the mask) addresses, which is the number of 1s in bitdiff
-            for num_of_addrs in 0..2usize.pow(bitdiff.count_ones()) {
-                // Calculate the address to write this time by flipping the relevant bits in the address, 2^bitdiff.count_ones() times
-                let addr_to_write = (0..bitdiff.count_ones() as usize)
-                    .fold(
-                        (addr | mask.0, bitdiff),
-                        |(temp_addr, temp_bitdiff), ones_counter| {
-                            (
-                                temp_addr
-                                    ^ bitdiff
-                                        & (temp_bitdiff
-                                            ^ (temp_bitdiff - (num_of_addrs >> ones_counter & 1))),
-                                temp_bitdiff & (temp_bitdiff - 1),
-                            )
-                        },
-                    )
-                    .0;
-                mem.insert(addr_to_write, val);
+pub struct V2;
+
+impl Decoder for V2 {
+    fn initial_mask(&self) -> Mask {
+        Mask {
+            ones: 0,
+            floating: 0,
+        }
+    }
+
+    fn decode(&self, mask: &Mask, addr: u64, value: u64) -> Vec<(u64, u64)> {
+        mask.floating_addresses(addr).map(|a| (a, value)).collect()
+    }
+}
+
+pub struct Interpreter<D: Decoder> {
+    decoder: D,
+    mask: Mask,
+    mem: HashMap<u64, u64>,
+}
+
+impl<D: Decoder> Interpreter<D> {
+    pub fn new(decoder: D) -> Interpreter<D> {
+        Interpreter {
+            mask: decoder.initial_mask(),
+            decoder,
+            mem: HashMap::new(),
+        }
+    }
+
+    pub fn mask(&self) -> &Mask {
+        &self.mask
+    }
+
+    pub fn memory(&self) -> &HashMap<u64, u64> {
+        &self.mem
+    }
+
+    pub fn sum(&self) -> u64 {
+        self.mem.values().sum()
+    }
+
+    // Returns the addresses the instruction wrote, none for a mask change
+    pub fn step(&mut self, instr: &DockingInstruction) -> Vec<u64> {
+        match *instr {
+            DockingInstruction::SetMask(mask) => {
+                self.mask = mask;
+                vec![]
+            }
+            DockingInstruction::Write { addr, value } => {
+                let mem = &mut self.mem;
+                self.decoder
+                    .decode(&self.mask, addr, value)
+                    .into_iter()
+                    .map(|(a, v)| {
+                        mem.insert(a, v);
+                        a
+                    })
+                    .collect()
             }
-        } else {
-            panic!("Unexpected line format.")
         }
-    });
-    mem.values().sum()
+    }
+
+    // The addresses written by each instruction, in program order
+    pub fn run(&mut self, program: &[DockingInstruction]) -> Vec<Vec<u64>> {
+        program.iter().map(|instr| self.step(instr)).collect()
+    }
+}
+
+pub fn execute<D: Decoder>(decoder: D, program: &[DockingInstruction]) -> u64 {
+    let mut interp = Interpreter::new(decoder);
+    interp.run(program);
+    interp.sum()
+}
+
+#[aoc(day14, part1)]
+pub fn part1(input: &[DockingInstruction]) -> u64 {
+    execute(V1, input)
+}
+
+// A set of addresses given as a ternary pattern: bits in floating take both values,
+// the rest come from fixed (which is always 0 under floating)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AddressPattern {
+    pub fixed: u64,
+    pub floating: u64,
+}
+
+impl AddressPattern {
+    pub fn new(mask: &Mask, addr: u64) -> AddressPattern {
+        AddressPattern {
+            fixed: (addr | mask.ones) & !mask.floating,
+            floating: mask.floating,
+        }
+    }
+
+    pub fn size(&self) -> u64 {
+        1 << self.floating.count_ones()
+    }
+
+    pub fn contains(&self, addr: u64) -> bool {
+        addr & !self.floating == self.fixed
+    }
+
+    // Two patterns overlap unless they disagree on a bit neither of them floats
+    pub fn intersects(&self, other: &AddressPattern) -> bool {
+        (self.fixed ^ other.fixed) & !self.floating & !other.floating == 0
+    }
+
+    // The addresses of self that are not in other, as disjoint patterns.
+    // Each bit other fixes but self floats splits off the half that disagrees with other,
+    // so there are never more pieces than floating bits
+    pub fn subtract(&self, other: &AddressPattern) -> Vec<AddressPattern> {
+        if !self.intersects(other) {
+            return vec![*self];
+        }
+        let mut rest = *self;
+        let mut pieces = vec![];
+        let mut split = self.floating & !other.floating;
+        while split != 0 {
+            let bit = split & split.wrapping_neg();
+            split &= split - 1;
+            rest.floating &= !bit;
+            pieces.push(AddressPattern {
+                fixed: rest.fixed | (!other.fixed & bit),
+                floating: rest.floating,
+            });
+            rest.fixed |= other.fixed & bit;
+        }
+        pieces
+    }
+}
+
+// Version 2 memory that never expands floating addresses.
+// It holds disjoint patterns, each with the value last written there: a new write is cut out of
+// every older pattern it overlaps, so the work grows with the number of writes and the bits
+// they fix, not with the 2^X addresses a write covers
+#[derive(Debug, Clone, Default)]
+pub struct SymbolicMemory {
+    cells: Vec<(AddressPattern, u64)>,
+}
+
+impl SymbolicMemory {
+    pub fn new() -> SymbolicMemory {
+        SymbolicMemory::default()
+    }
+
+    pub fn write(&mut self, pattern: AddressPattern, value: u64) {
+        let mut cells = Vec::with_capacity(self.cells.len() + 1);
+        self.cells.iter().for_each(|&(p, v)| {
+            cells.extend(p.subtract(&pattern).into_iter().map(|piece| (piece, v)));
+        });
+        cells.push((pattern, value));
+        self.cells = cells;
+    }
+
+    pub fn run(program: &[DockingInstruction]) -> SymbolicMemory {
+        let mut mem = SymbolicMemory::new();
+        let mut mask = V2.initial_mask();
+        program.iter().for_each(|instr| match *instr {
+            DockingInstruction::SetMask(m) => mask = m,
+            DockingInstruction::Write { addr, value } => {
+                mem.write(AddressPattern::new(&mask, addr), value)
+            }
+        });
+        mem
+    }
+
+    pub fn get(&self, addr: u64) -> Option<u64> {
+        self.cells
+            .iter()
+            .find(|(p, _)| p.contains(addr))
+            .map(|&(_, v)| v)
+    }
+
+    // How many patterns are stored
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    // How many addresses hold a value
+    pub fn address_count(&self) -> u64 {
+        self.cells.iter().map(|(p, _)| p.size()).sum()
+    }
+
+    // 2^36 addresses of 36 bit values can go past a u64
+    pub fn sum(&self) -> u128 {
+        self.cells
+            .iter()
+            .map(|&(p, v)| p.size() as u128 * v as u128)
+            .sum()
+    }
+}
+
+#[aoc(day14, part2)]
+pub fn part2(input: &[DockingInstruction]) -> u128 {
+    SymbolicMemory::run(input).sum()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    const INPUT: &str = "1 2 3";
     const INPUT2: &str = "mask = XXXXXXXXXXXXXXXXXXXXXXXXXXXXX1XXXX0X
 mem[8] = 11
 mem[7] = 101
@@ -97,19 +368,126 @@ mem[26] = 1";
 
     #[test]
     pub fn test_generator() {
+        let program = input_generator(INPUT2).unwrap();
+        assert_eq!(program.len(), 4);
+        assert_eq!(
+            program[0],
+            DockingInstruction::SetMask(Mask {
+                ones: 0b1000000,
+                floating: WORD & !0b1000010
+            })
+        );
+        assert_eq!(program[1], DockingInstruction::Write { addr: 8, value: 11 });
         assert_eq!(
-            input_generator(INPUT),
-            vec![String::from("1"), String::from("2"), String::from("3")]
+            "XXXXXXXXXXXXXXXXXXXXXXXXXXXXX1XXXX0X"
+                .parse::<Mask>()
+                .unwrap()
+                .to_string(),
+            "XXXXXXXXXXXXXXXXXXXXXXXXXXXXX1XXXX0X"
         );
+
+        assert!(input_generator("mask = 1X0").is_err());
+        assert!(input_generator("mask = 00000000000000000000000000000000X0X2").is_err());
+        assert!(input_generator("mem[68719476736] = 1").is_err());
+        assert!(input_generator("mem[1] = 1\nmov 1").is_err());
     }
 
     #[test]
     pub fn test_part1() {
-        assert_eq!(part1(&input_generator(INPUT2)), 165);
+        assert_eq!(part1(&input_generator(INPUT2).unwrap()), 165);
     }
 
     #[test]
     pub fn test_part2() {
-        assert_eq!(part2(&input_generator(INPUT3)), 208);
+        assert_eq!(part2(&input_generator(INPUT3).unwrap()), 208);
+    }
+
+    #[test]
+    pub fn test_written_addresses() {
+        let program = input_generator(INPUT3).unwrap();
+        let mut v2 = Interpreter::new(V2);
+        assert_eq!(
+            v2.run(&program),
+            vec![
+                vec![],
+                vec![26, 27, 58, 59],
+                vec![],
+                vec![16, 17, 18, 19, 24, 25, 26, 27]
+            ]
+        );
+        assert_eq!(v2.memory()[&58], 100);
+        assert_eq!(v2.memory()[&26], 1);
+
+        let mut v1 = Interpreter::new(V1);
+        assert_eq!(
+            v1.run(&input_generator(INPUT2).unwrap())[1..],
+            [vec![8], vec![7], vec![8]]
+        );
+        assert_eq!(v1.memory()[&7], 101);
+
+        // Without a mask both versions store writes as they come
+        let plain = input_generator("mem[3] = 9").unwrap();
+        assert_eq!(execute(V1, &plain), 9);
+        assert_eq!(execute(V2, &plain), 9);
+    }
+
+    #[test]
+    pub fn test_symbolic_memory() {
+        let program = input_generator(INPUT3).unwrap();
+        let mem = SymbolicMemory::run(&program);
+        assert_eq!(mem.address_count(), 10);
+        assert_eq!(mem.get(58), Some(100));
+        assert_eq!(mem.get(26), Some(1));
+        assert_eq!(mem.get(42), None);
+
+        // Overlaps of every shape give the same answer as expanding each address
+        let program = input_generator(
+            "mask = 0000000000000000000000000000000XX1XX
+mem[3] = 5
+mask = 00000000000000000000000000000000X0X1
+mem[8] = 7
+mem[2] = 11
+mask = 000000000000000000000000000000XXXXXX
+mem[0] = 2
+mask = 000000000000000000000000000000000X1X
+mem[40] = 13",
+        )
+        .unwrap();
+        assert_eq!(
+            SymbolicMemory::run(&program).sum(),
+            execute(V2, &program) as u128
+        );
+
+        // Every address at once is still a single pattern
+        let program = input_generator(
+            "mask = XXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXX
+mem[0] = 68719476735
+mask = 00000000000000000000000000000000000X
+mem[0] = 1",
+        )
+        .unwrap();
+        let mem = SymbolicMemory::run(&program);
+        assert!(mem.len() <= 37);
+        assert_eq!(mem.address_count(), 1 << 36);
+        assert_eq!(mem.sum(), ((1u128 << 36) - 2) * WORD as u128 + 2);
+    }
+
+    #[test]
+    pub fn test_subtract() {
+        let a = AddressPattern {
+            fixed: 0,
+            floating: 0b111,
+        };
+        let b = AddressPattern {
+            fixed: 0b101,
+            floating: 0b010,
+        };
+        let pieces = a.subtract(&b);
+        assert_eq!(pieces.iter().map(|p| p.size()).sum::<u64>(), 6);
+        assert!((0..8).all(|addr| {
+            let n = pieces.iter().filter(|p| p.contains(addr)).count();
+            n == if b.contains(addr) { 0 } else { 1 }
+        }));
+        assert_eq!(b.subtract(&a), vec![]);
     }
 }
@@ -1,62 +1,174 @@
 use aoc_runner_derive::{aoc, aoc_generator};
 use std::collections::HashMap;
+use std::num::ParseIntError;
 
 #[aoc_generator(day15)]
-pub fn input_generator(input: &str) -> HashMap<usize, usize> {
-    let mut res: HashMap<usize, usize> = HashMap::new();
-    let mut counter = 1;
-    input.split(',').for_each(|s| {
-        let num = s.parse::<usize>().unwrap();
-        res.insert(num, counter);
-        counter += 1;
-    });
-    res
-}
-
-pub fn solver(limit: usize, input: &HashMap<usize, usize>) -> usize {
-    let mut hm = input.clone();
-
-    /*     let mut counter = input.len();
-       let mut lastspoken = 0;
-       let mut nowspoken = 0;
-    */
-
-    (input.len() + 1..limit).fold(0, |spoken, count| match hm.get(&spoken) {
-        Some(&val) => {
-            hm.insert(spoken, count);
-            count - val
+pub fn input_generator(input: &str) -> Result<Vec<u32>, ParseIntError> {
+    input
+        .trim()
+        .split(',')
+        .map(|s| s.parse::<u32>())
+        .collect::<Result<Vec<u32>, ParseIntError>>()
+}
+
+// Remembers the last turn each number was spoken on
+pub trait SpokenMemory {
+    // Records n as spoken on this turn, and returns the turn it was spoken on before, if ever
+    fn speak(&mut self, n: u32, turn: u32) -> Option<u32>;
+}
+
+// The original approach, kept around for comparison
+#[derive(Debug, Clone, Default)]
+pub struct HashMemory(HashMap<u32, u32>);
+
+impl SpokenMemory for HashMemory {
+    fn speak(&mut self, n: u32, turn: u32) -> Option<u32> {
+        self.0.insert(n, turn)
+    }
+}
+
+// A number spoken after turn t is at most t, so a Vec as long as the game covers everything
+// with one indexed load and store per turn, 0 standing for "never spoken".
+// Numbers past the end of the Vec (large starting numbers, or games longer than planned for)
+// go to a HashMap instead
+#[derive(Debug, Clone, Default)]
+pub struct FlatMemory {
+    dense: Vec<u32>,
+    sparse: HashMap<u32, u32>,
+}
+
+impl FlatMemory {
+    pub fn with_capacity(capacity: usize) -> FlatMemory {
+        FlatMemory {
+            dense: vec![0; capacity],
+            sparse: HashMap::new(),
+        }
+    }
+}
+
+impl SpokenMemory for FlatMemory {
+    fn speak(&mut self, n: u32, turn: u32) -> Option<u32> {
+        match self.dense.get_mut(n as usize) {
+            Some(slot) => {
+                let prev = *slot;
+                *slot = turn;
+                if prev == 0 {
+                    None
+                } else {
+                    Some(prev)
+                }
+            }
+            None => self.sparse.insert(n, turn),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Stats {
+    // Turn each number was first spoken on
+    pub first_spoken: HashMap<u32, u32>,
+    // (number, turn, gap): the longest wait between two turns speaking the same number
+    pub largest_gap: Option<(u32, u32, u32)>,
+}
+
+// The spoken sequence, one number per turn, starting at turn 1
+pub struct VanEck<M: SpokenMemory> {
+    start: Vec<u32>,
+    memory: M,
+    turn: u32,
+    next: u32,
+    stats: Option<Stats>,
+}
+
+impl<M: SpokenMemory> VanEck<M> {
+    pub fn new(start: &[u32], memory: M) -> VanEck<M> {
+        VanEck {
+            start: start.to_vec(),
+            memory,
+            turn: 0,
+            next: 0,
+            stats: None,
         }
-        None => {
-            hm.insert(spoken, count);
-            0
+    }
+
+    // Keep statistics while playing; costs a HashMap insert for every new number
+    pub fn with_stats(mut self) -> VanEck<M> {
+        self.stats = Some(Stats::default());
+        self
+    }
+
+    pub fn stats(&self) -> Option<&Stats> {
+        self.stats.as_ref()
+    }
+
+    // How many turns have been played
+    pub fn turn(&self) -> u32 {
+        self.turn
+    }
+}
+
+impl<M: SpokenMemory> Iterator for VanEck<M> {
+    type Item = u32;
+
+    fn next(&mut self) -> Option<u32> {
+        self.turn += 1;
+        let turn = self.turn;
+        let spoken = match self.start.get(turn as usize - 1) {
+            Some(&n) => n,
+            None => self.next,
+        };
+        let prev = self.memory.speak(spoken, turn);
+        self.next = prev.map_or(0, |p| turn - p);
+        if let Some(stats) = &mut self.stats {
+            match prev {
+                None => {
+                    stats.first_spoken.insert(spoken, turn);
+                }
+                Some(p) => {
+                    if stats.largest_gap.is_none_or(|(_, _, gap)| turn - p > gap) {
+                        stats.largest_gap = Some((spoken, turn, turn - p));
+                    }
+                }
+            }
         }
-    })
+        Some(spoken)
+    }
+}
+
+// The number spoken on the given turn, counting from 1
+pub fn nth_spoken(start: &[u32], turn: u32) -> Option<u32> {
+    let memory = FlatMemory::with_capacity(turn as usize);
+    VanEck::new(start, memory).nth(turn.checked_sub(1)? as usize)
+}
 
-    /*     while counter < limit {
-           lastspoken = nowspoken;
-           counter += 1;
+pub fn solver(limit: u32, input: &[u32]) -> Option<u32> {
+    nth_spoken(input, limit)
+}
 
-           if let Some(&val) = hm.get(&lastspoken) {
-               nowspoken = counter - val;
-           } else {
-               nowspoken = 0;
-           }
-           hm.insert(lastspoken, counter);
-       }
-       lastspoken
-    */
+pub fn solver_hashmap(limit: u32, input: &[u32]) -> Option<u32> {
+    VanEck::new(input, HashMemory::default()).nth(limit.checked_sub(1)? as usize)
 }
 
 #[aoc(day15, part1)]
-pub fn part1(input: &HashMap<usize, usize>) -> usize {
+pub fn part1(input: &[u32]) -> Option<u32> {
     solver(2020, input)
 }
 
 #[aoc(day15, part2)]
-pub fn part2(input: &HashMap<usize, usize>) -> usize {
+pub fn part2(input: &[u32]) -> Option<u32> {
     solver(30000000, input)
 }
 
+#[aoc(day15, part1, HashMap)]
+pub fn part1_hashmap(input: &[u32]) -> Option<u32> {
+    solver_hashmap(2020, input)
+}
+
+#[aoc(day15, part2, HashMap)]
+pub fn part2_hashmap(input: &[u32]) -> Option<u32> {
+    solver_hashmap(30000000, input)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -65,35 +177,58 @@ mod tests {
     const INPUT2: [&str; 7] = [
         "0,3,6", "1,3,2", "2,1,3", "1,2,3", "2,3,1", "3,2,1", "3,1,2",
     ];
-    const RESULT2: [usize; 7] = [436, 1, 10, 27, 78, 438, 1836];
-    const RESULT3: [usize; 7] = [175594, 2578, 3544142, 261214, 6895259, 18, 362];
+    const RESULT2: [u32; 7] = [436, 1, 10, 27, 78, 438, 1836];
+    const RESULT3: [u32; 7] = [175594, 2578, 3544142, 261214, 6895259, 18, 362];
 
     #[test]
     pub fn test_generator() {
-        assert_eq!(
-            input_generator(INPUT1),
-            [(1, 1), (2, 2), (3, 3)]
-                .iter()
-                .cloned()
-                .collect::<HashMap<usize, usize>>()
-        );
+        assert_eq!(input_generator(INPUT1).unwrap(), vec![1, 2, 3]);
+        assert!(input_generator("1,,3").is_err());
     }
 
     #[test]
     pub fn test_part1() {
         INPUT2.iter().zip(RESULT2.iter()).for_each(|(&s, &res)| {
-            assert_eq!(part1(&input_generator(s)), res);
+            assert_eq!(part1(&input_generator(s).unwrap()), Some(res));
+            assert_eq!(part1_hashmap(&input_generator(s).unwrap()), Some(res));
         });
     }
     #[test]
     pub fn test_part2() {
         INPUT2.iter().zip(RESULT3.iter()).for_each(|(&s, &res)| {
-            assert_eq!(part2(&input_generator(s)), res);
+            assert_eq!(part2(&input_generator(s).unwrap()), Some(res));
         });
     }
 
     #[test]
     pub fn test_part2_t1() {
-        assert_eq!(part2(&input_generator("0,3,6")), 175594);
+        assert_eq!(part2(&input_generator("0,3,6").unwrap()), Some(175594));
+    }
+
+    #[test]
+    pub fn test_sequence() {
+        let seq = VanEck::new(&[0, 3, 6], FlatMemory::default())
+            .take(10)
+            .collect::<Vec<u32>>();
+        assert_eq!(seq, vec![0, 3, 6, 0, 3, 3, 1, 0, 4, 0]);
+        assert_eq!(nth_spoken(&[0, 3, 6], 9), Some(4));
+        assert_eq!(nth_spoken(&[0, 3, 6], 0), None);
+
+        // Repeats among the starting numbers count, and big ones fall back to the HashMap
+        let seq = VanEck::new(&[1, 1, 4_000_000_000], FlatMemory::with_capacity(8))
+            .take(6)
+            .collect::<Vec<u32>>();
+        assert_eq!(seq, vec![1, 1, 4_000_000_000, 0, 0, 1]);
+    }
+
+    #[test]
+    pub fn test_stats() {
+        let mut game = VanEck::new(&[0, 3, 6], FlatMemory::default()).with_stats();
+        game.by_ref().take(10).for_each(drop);
+        assert_eq!(game.turn(), 10);
+        let stats = game.stats().unwrap();
+        assert_eq!(stats.first_spoken.get(&1), Some(&7));
+        assert_eq!(stats.first_spoken.get(&4), Some(&9));
+        assert_eq!(stats.largest_gap, Some((0, 8, 4)));
     }
 }
@@ -1,196 +1,348 @@
 use aoc_runner_derive::{aoc, aoc_generator};
+use lazy_static::lazy_static;
 use regex::Regex;
-use std::collections::{HashMap, HashSet};
+use simple_error::*;
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
 
-// The HashMap solution and the bitmask solution are both possible
-// This is the HashMap solution
+lazy_static! {
+    static ref RULE: Regex = Regex::new(r"^([^:]+): (.+)$").unwrap();
+    static ref RANGE: Regex = Regex::new(r"^(\d+)-(\d+)$").unwrap();
+}
 
-#[derive(Debug, Clone)]
+// A field and the inclusive ranges its values may fall in, any number of them
+#[derive(Debug, Clone, PartialEq)]
 pub struct Rule {
-    name: String,
-    min1: usize,
-    max1: usize,
-    min2: usize,
-    max2: usize,
+    pub name: String,
+    pub ranges: Vec<(u64, u64)>,
 }
 
-#[aoc_generator(day16)]
-pub fn input_generator(input: &str) -> (Vec<Rule>, Vec<usize>, Vec<Vec<usize>>) {
-    let firstsplit = &input
-        .split("\n\n")
-        .map(|s| String::from(s))
-        .collect::<Vec<String>>();
-
-    let rules = firstsplit[0].clone();
-    let myticket = firstsplit[1].clone();
-    let neartickets = firstsplit[2].clone();
-
-    let rules_matcher =
-        Regex::new(r"^([a-z ]+): (\d{1,3})-(\d{1,3}) or (\d{1,3})-(\d{1,3})$").unwrap();
-    let rules = rules
-        .lines()
-        .map(|l| {
-            if let Some(caps) = rules_matcher.captures(l) {
-                Rule {
-                    name: String::from(caps.get(1).unwrap().as_str()),
-                    min1: caps.get(2).unwrap().as_str().parse::<usize>().unwrap(),
-                    max1: caps.get(3).unwrap().as_str().parse::<usize>().unwrap(),
-                    min2: caps.get(4).unwrap().as_str().parse::<usize>().unwrap(),
-                    max2: caps.get(5).unwrap().as_str().parse::<usize>().unwrap(),
+impl Rule {
+    pub fn matches(&self, n: u64) -> bool {
+        self.ranges.iter().any(|&(lo, hi)| lo <= n && n <= hi)
+    }
+}
+
+impl FromStr for Rule {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let cap = match RULE.captures(s) {
+            Some(cap) => cap,
+            None => bail!("bad rule: {}", s),
+        };
+        let ranges = cap[2]
+            .split(" or ")
+            .map(|r| {
+                let rc = match RANGE.captures(r) {
+                    Some(rc) => rc,
+                    None => bail!("bad range in rule: {}", s),
+                };
+                let (lo, hi) = (rc[1].parse::<u64>()?, rc[2].parse::<u64>()?);
+                if lo > hi {
+                    bail!("empty range {} in rule: {}", r, s);
                 }
-            } else {
-                panic!("Isn't supposed to happen.")
-            }
+                Ok((lo, hi))
+            })
+            .collect::<Result<Vec<(u64, u64)>, Box<dyn Error>>>()?;
+        Ok(Rule {
+            name: String::from(&cap[1]),
+            ranges,
         })
-        .collect();
+    }
+}
 
-    let myticket = myticket
-        .lines()
-        .skip(1)
-        .next()
-        .unwrap()
-        .split(',')
-        .map(|s| s.parse::<usize>().unwrap())
-        .collect();
+// The union of many ranges as sorted, disjoint, non-adjacent intervals,
+// so a value is checked with one binary search
+#[derive(Debug, Clone, PartialEq)]
+pub struct IntervalSet {
+    intervals: Vec<(u64, u64)>,
+}
 
-    let neartickets = neartickets
-        .lines()
-        .skip(1)
-        .map(|l| {
-            l.split(',')
-                .map(|li| li.parse::<usize>().unwrap())
-                .collect::<Vec<usize>>()
-        })
-        .collect();
+impl IntervalSet {
+    pub fn new(ranges: impl IntoIterator<Item = (u64, u64)>) -> IntervalSet {
+        let mut sorted = ranges.into_iter().collect::<Vec<(u64, u64)>>();
+        sorted.sort_unstable();
+        let mut intervals: Vec<(u64, u64)> = Vec::with_capacity(sorted.len());
+        sorted
+            .into_iter()
+            .for_each(|(lo, hi)| match intervals.last_mut() {
+                Some(last) if lo <= last.1.saturating_add(1) => last.1 = last.1.max(hi),
+                _ => intervals.push((lo, hi)),
+            });
+        IntervalSet { intervals }
+    }
+
+    pub fn from_rules(rules: &[Rule]) -> IntervalSet {
+        IntervalSet::new(rules.iter().flat_map(|r| r.ranges.iter().copied()))
+    }
+
+    pub fn intervals(&self) -> &[(u64, u64)] {
+        &self.intervals
+    }
 
-    (rules, myticket, neartickets)
+    pub fn contains(&self, n: u64) -> bool {
+        // The first interval ending at or after n is the only one that can hold it
+        let i = self.intervals.partition_point(|&(_, hi)| hi < n);
+        self.intervals.get(i).is_some_and(|&(lo, _)| lo <= n)
+    }
 }
 
-#[aoc(day16, part1)]
-pub fn part1((rules, _myticket, neartickets): &(Vec<Rule>, Vec<usize>, Vec<Vec<usize>>)) -> usize {
-    // Build hashmap with all the values
-    let mut set: HashSet<usize> = HashSet::new();
-    rules.iter().for_each(|r| {
-        (r.min1..=r.max1).for_each(|n| {
-            set.insert(n);
-        });
-        (r.min2..=r.max2).for_each(|n| {
-            set.insert(n);
-        });
-    });
+#[derive(Debug, Clone, PartialEq)]
+pub struct Notes {
+    pub rules: Vec<Rule>,
+    pub mine: Vec<u64>,
+    pub nearby: Vec<Vec<u64>>,
+}
 
-    neartickets.iter().fold(0, |te, tv| {
-        te + tv.iter().fold(
-            0,
-            |tei, num| {
-                if !set.contains(num) {
-                    tei + num
-                } else {
-                    tei
-                }
-            },
-        )
-    })
+fn parse_ticket(l: &str) -> Result<Vec<u64>, Box<dyn Error>> {
+    Ok(l.split(',')
+        .map(|s| s.parse::<u64>())
+        .collect::<Result<Vec<u64>, _>>()?)
 }
 
-pub fn get_invalid_tickets(
-    rules: &Vec<Rule>,
-    neartickets: &Vec<Vec<usize>>,
-) -> HashSet<Vec<usize>> {
-    // Build hashmap with all the values
-    let mut set: HashSet<usize> = HashSet::new();
-    rules.iter().for_each(|r| {
-        (r.min1..=r.max1).for_each(|n| {
-            set.insert(n);
-        });
-        (r.min2..=r.max2).for_each(|n| {
-            set.insert(n);
-        });
-    });
+#[aoc_generator(day16)]
+pub fn input_generator(input: &str) -> Result<Notes, Box<dyn Error>> {
+    let sections = input.split("\n\n").collect::<Vec<&str>>();
+    if sections.len() != 3 {
+        bail!("expected rules, your ticket and nearby tickets");
+    }
 
-    neartickets.iter().fold(HashSet::new(), |mut acc, tv| {
-        tv.iter().for_each(|t| {
-            if !set.contains(t) {
-                acc.insert(tv.clone());
-            }
-        });
-        acc
+    let rules = sections[0]
+        .lines()
+        .map(|l| l.parse::<Rule>())
+        .collect::<Result<Vec<Rule>, Box<dyn Error>>>()?;
+
+    let mine = match sections[1].lines().nth(1) {
+        Some(l) => parse_ticket(l)?,
+        None => bail!("your ticket is missing"),
+    };
+
+    let nearby = sections[2]
+        .lines()
+        .skip(1)
+        .map(parse_ticket)
+        .collect::<Result<Vec<Vec<u64>>, Box<dyn Error>>>()?;
+
+    Ok(Notes {
+        rules,
+        mine,
+        nearby,
     })
 }
 
-// Copied from here: https://stackoverflow.com/questions/64498617/how-to-transpose-a-vector-of-vectors-in-rust
-pub fn transpose<T>(v: &Vec<Vec<T>>) -> Vec<Vec<T>>
-where
-    T: Clone,
-{
-    assert!(!v.is_empty());
-    (0..v[0].len())
-        .map(|i| v.iter().map(|inner| inner[i].clone()).collect::<Vec<T>>())
-        .collect()
+// A nearby ticket with the (position, value) of every value no rule allows
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidTicket {
+    pub index: usize,
+    pub values: Vec<(usize, u64)>,
 }
 
-#[aoc(day16, part2)]
-pub fn part2((rules, myticket, neartickets): &(Vec<Rule>, Vec<usize>, Vec<Vec<usize>>)) -> usize {
-    // Get invalid tickets
-    let invalid_tickets = get_invalid_tickets(rules, neartickets);
+pub fn invalid_tickets(rules: &[Rule], nearby: &[Vec<u64>]) -> Vec<InvalidTicket> {
+    let allowed = IntervalSet::from_rules(rules);
+    nearby
+        .iter()
+        .enumerate()
+        .filter_map(|(index, t)| {
+            let values = t
+                .iter()
+                .copied()
+                .enumerate()
+                .filter(|&(_, n)| !allowed.contains(n))
+                .collect::<Vec<(usize, u64)>>();
+            if values.is_empty() {
+                None
+            } else {
+                Some(InvalidTicket { index, values })
+            }
+        })
+        .collect()
+}
 
-    // Transpose the vectors to put the values to check in the same vector
-    let cols = transpose(
-        &neartickets
-            .iter()
-            .filter(|&t| !invalid_tickets.contains(t))
-            .cloned()
-            .collect::<Vec<Vec<usize>>>(),
-    );
+#[aoc(day16, part1)]
+pub fn part1(notes: &Notes) -> u64 {
+    invalid_tickets(&notes.rules, &notes.nearby)
+        .iter()
+        .flat_map(|t| t.values.iter().map(|&(_, n)| n))
+        .sum()
+}
 
-    // Find all the rules that match particular column
-    let mut fields_mapping = cols
+// For each column, the rules every valid nearby ticket satisfies there
+pub fn candidates(notes: &Notes) -> Vec<Vec<usize>> {
+    let invalid = invalid_tickets(&notes.rules, &notes.nearby)
+        .into_iter()
+        .map(|t| t.index)
+        .collect::<HashSet<usize>>();
+    let valid = notes
+        .nearby
         .iter()
         .enumerate()
-        .fold(HashMap::new(), |mut hm, (col, v)| {
-            let rulematch: Vec<String> = rules
+        .filter(|(i, _)| !invalid.contains(i))
+        .map(|(_, t)| t)
+        .collect::<Vec<&Vec<u64>>>();
+
+    (0..notes.mine.len())
+        .map(|col| {
+            notes
+                .rules
                 .iter()
-                .filter(|&r| {
-                    v.iter().fold(true, |acc, &n| {
-                        acc && (((n >= r.min1) && (n <= r.max1))
-                            || ((n >= r.min2) && (n <= r.max2)))
-                    })
+                .enumerate()
+                .filter(|(_, r)| {
+                    valid
+                        .iter()
+                        .all(|t| t.get(col).is_some_and(|&n| r.matches(n)))
                 })
-                .map(|r| r.name.clone())
-                .collect();
+                .map(|(i, _)| i)
+                .collect()
+        })
+        .collect()
+}
 
-            hm.insert(col, rulematch);
-            hm
-        });
+// Which field each column of a ticket holds
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldMapping {
+    fields: Vec<String>,
+}
+
+impl FieldMapping {
+    // Field names in column order
+    pub fn fields(&self) -> &[String] {
+        &self.fields
+    }
+
+    pub fn field(&self, col: usize) -> Option<&str> {
+        self.fields.get(col).map(|s| s.as_str())
+    }
+
+    pub fn column(&self, name: &str) -> Option<usize> {
+        self.fields.iter().position(|f| f == name)
+    }
+
+    // (column, field) for every field whose name starts with prefix
+    pub fn with_prefix<'a>(&'a self, prefix: &'a str) -> impl Iterator<Item = (usize, &'a str)> {
+        self.fields
+            .iter()
+            .enumerate()
+            .filter(move |(_, f)| f.starts_with(prefix))
+            .map(|(col, f)| (col, f.as_str()))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AssignmentError {
+    // Even the best matching leaves these columns without a field
+    Impossible {
+        columns: Vec<usize>,
+    },
+    // A matching exists, but these columns could take another field in a different one
+    Ambiguous {
+        mapping: FieldMapping,
+        columns: Vec<usize>,
+    },
+}
+
+impl fmt::Display for AssignmentError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AssignmentError::Impossible { columns } => {
+                write!(f, "no field fits columns {:?}", columns)
+            }
+            AssignmentError::Ambiguous { columns, .. } => {
+                write!(f, "columns {:?} fit more than one field", columns)
+            }
+        }
+    }
+}
+
+impl Error for AssignmentError {}
+
+// Kuhn's augmenting path search: try to give col a field, moving other columns out of the way
+fn augment(
+    col: usize,
+    cands: &[Vec<usize>],
+    col_of_field: &mut [Option<usize>],
+    seen: &mut [bool],
+) -> bool {
+    for &f in &cands[col] {
+        if seen[f] {
+            continue;
+        }
+        seen[f] = true;
+        if col_of_field[f].is_none_or(|other| augment(other, cands, col_of_field, seen)) {
+            col_of_field[f] = Some(col);
+            return true;
+        }
+    }
+    false
+}
+
+// Finds a matching of columns to rules covering every column, then checks it is the only one.
+// Another matching exists exactly when, moving from a column to the column currently holding
+// one of its other candidates, we can come back around or reach a field nobody holds
+pub fn assign(cands: &[Vec<usize>], rules: &[Rule]) -> Result<FieldMapping, AssignmentError> {
+    let mut col_of_field: Vec<Option<usize>> = vec![None; rules.len()];
+    let unmatched = (0..cands.len())
+        .filter(|&col| !augment(col, cands, &mut col_of_field, &mut vec![false; rules.len()]))
+        .collect::<Vec<usize>>();
+    if !unmatched.is_empty() {
+        return Err(AssignmentError::Impossible { columns: unmatched });
+    }
 
-    // Now reduce fields_mapping like this:
-    // 1. find the field mapping where only one match exists
-    // 2. remove that match from all other field mappings
-    // 3. repeat the above until no more field mappings are left
-    let mut final_mapping: HashMap<usize, String> = HashMap::new();
-    while fields_mapping.len() > 0 {
-        let sfm = fields_mapping.iter().find(|&(_, mv)| mv.len() == 1);
-        if let Some((col, vs)) = sfm {
-            final_mapping.insert(*col, vs[0].clone());
+    let mut field_of_col = vec![0; cands.len()];
+    col_of_field.iter().enumerate().for_each(|(f, c)| {
+        if let Some(c) = c {
+            field_of_col[*c] = f;
         }
-        final_mapping.keys().for_each(|k| {
-            fields_mapping.remove(k);
-            // Now remove from vectors inside
-            fields_mapping.iter_mut().for_each(|(_, s)| {
-                let oidx = s.iter().position(|x| x == &final_mapping[k]);
-                if let Some(idx) = oidx {
-                    s.remove(idx);
+    });
+    let mapping = FieldMapping {
+        fields: field_of_col
+            .iter()
+            .map(|&f| rules[f].name.clone())
+            .collect(),
+    };
+
+    let ambiguous = (0..cands.len())
+        .filter(|&start| {
+            let mut seen = vec![false; cands.len()];
+            let mut stack = vec![start];
+            while let Some(col) = stack.pop() {
+                for &f in cands[col].iter().filter(|&&f| f != field_of_col[col]) {
+                    match col_of_field[f] {
+                        None => return true,
+                        Some(next) if next == start => return true,
+                        Some(next) if !seen[next] => {
+                            seen[next] = true;
+                            stack.push(next);
+                        }
+                        _ => {}
+                    }
                 }
-            });
+            }
+            false
+        })
+        .collect::<Vec<usize>>();
+    if !ambiguous.is_empty() {
+        return Err(AssignmentError::Ambiguous {
+            mapping,
+            columns: ambiguous,
         });
     }
+    Ok(mapping)
+}
 
-    myticket
-        .iter()
-        .enumerate()
-        .filter(|(i, _)| final_mapping[i].starts_with("departure"))
-        .map(|x| x.1)
-        .product()
+pub fn assign_fields(notes: &Notes) -> Result<FieldMapping, AssignmentError> {
+    assign(&candidates(notes), &notes.rules)
+}
+
+#[aoc(day16, part2)]
+pub fn part2(notes: &Notes) -> Result<u64, AssignmentError> {
+    let mapping = assign_fields(notes)?;
+    Ok(mapping
+        .with_prefix("departure")
+        .map(|(col, _)| notes.mine[col])
+        .product())
 }
 
 #[cfg(test)]
@@ -228,13 +380,116 @@ nearby tickets:
        }
     */
 
+    #[test]
+    pub fn test_generator() {
+        let notes = input_generator(INPUT).unwrap();
+        assert_eq!(
+            notes.rules[1],
+            Rule {
+                name: String::from("row"),
+                ranges: vec![(6, 11), (33, 44)]
+            }
+        );
+        assert_eq!(notes.mine, vec![7, 1, 14]);
+        assert_eq!(notes.nearby.len(), 4);
+
+        let rule = "wide field: 1-2 or 1000-99999 or 5000000000-6000000000"
+            .parse::<Rule>()
+            .unwrap();
+        assert_eq!(rule.ranges.len(), 3);
+        assert!(rule.matches(5_500_000_000));
+        assert!("bad: 5-3".parse::<Rule>().is_err());
+        assert!("bad: 1-3 and 5-7".parse::<Rule>().is_err());
+        assert!(input_generator("class: 1-3 or 5-7\n\nyour ticket:\n1,x").is_err());
+    }
+
     #[test]
     pub fn test_part1() {
-        assert_eq!(part1(&input_generator(INPUT)), 71);
+        assert_eq!(part1(&input_generator(INPUT).unwrap()), 71);
     }
 
     #[test]
     pub fn test_part2() {
-        assert_eq!(part2(&input_generator(INPUT2)), 1716);
+        assert_eq!(part2(&input_generator(INPUT2).unwrap()), Ok(1716));
+    }
+
+    #[test]
+    pub fn test_interval_set() {
+        let notes = input_generator(INPUT).unwrap();
+        let set = IntervalSet::from_rules(&notes.rules);
+        assert_eq!(set.intervals(), &[(1, 3), (5, 11), (13, 50)]);
+        assert!(!set.contains(0));
+        assert!(set.contains(1));
+        assert!(!set.contains(4));
+        assert!(set.contains(11));
+        assert!(!set.contains(12));
+        assert!(!set.contains(51));
+        assert_eq!(
+            IntervalSet::new(vec![(10, 20), (0, 4), (5, 6), (15, 18), (30, u64::MAX)]).intervals(),
+            &[(0, 6), (10, 20), (30, u64::MAX)]
+        );
+
+        assert_eq!(
+            invalid_tickets(&notes.rules, &notes.nearby),
+            vec![
+                InvalidTicket {
+                    index: 1,
+                    values: vec![(1, 4)]
+                },
+                InvalidTicket {
+                    index: 2,
+                    values: vec![(0, 55)]
+                },
+                InvalidTicket {
+                    index: 3,
+                    values: vec![(2, 12)]
+                },
+            ]
+        );
+    }
+
+    #[test]
+    pub fn test_assignment() {
+        let notes = input_generator(INPUT2).unwrap();
+        let mapping = assign_fields(&notes).unwrap();
+        assert_eq!(
+            mapping.fields(),
+            &["departure row", "departure class", "departure seat"]
+        );
+        assert_eq!(mapping.field(2), Some("departure seat"));
+        assert_eq!(mapping.column("departure class"), Some(1));
+        assert_eq!(
+            mapping.with_prefix("departure s").collect::<Vec<_>>(),
+            vec![(2, "departure seat")]
+        );
+
+        // Two rules that accept the same values can swap columns
+        let notes = input_generator(
+            "a: 0-10\nb: 0-10\nc: 20-30\n\nyour ticket:\n1,2,25\n\nnearby tickets:\n3,4,21",
+        )
+        .unwrap();
+        match assign_fields(&notes) {
+            Err(AssignmentError::Ambiguous { columns, .. }) => assert_eq!(columns, vec![0, 1]),
+            other => panic!("expected ambiguity, got {:?}", other),
+        }
+
+        // A spare rule fitting a column is another way to fill it
+        let cands = vec![vec![0], vec![1, 2]];
+        let rules = input_generator("a: 0-1\nb: 0-1\nc: 0-1\n\nyour ticket:\n0\n\nnearby tickets:")
+            .unwrap()
+            .rules;
+        match assign(&cands, &rules) {
+            Err(AssignmentError::Ambiguous { columns, .. }) => assert_eq!(columns, vec![1]),
+            other => panic!("expected ambiguity, got {:?}", other),
+        }
+
+        // Two columns only the same rule fits
+        let notes =
+            input_generator("a: 0-10\nb: 20-30\n\nyour ticket:\n1,2\n\nnearby tickets:\n3,4")
+                .unwrap();
+        assert_eq!(
+            assign_fields(&notes),
+            Err(AssignmentError::Impossible { columns: vec![1] })
+        );
     }
 }
@@ -1,231 +1,365 @@
 use aoc_runner_derive::{aoc, aoc_generator};
-use ndarray::{s, Array2, Array3, Array4};
-
-// Bitmap solution is possible
-// But I want to play with ndarrays - never had the chance to do so in Rust
-// Perhaps, even rayon and parallel iterators
-
-#[aoc_generator(day17, part1)]
-pub fn input_generator(input: &str) -> Array3<usize> {
-    let input = input
-        .lines()
-        .flat_map(|s| {
-            s.chars().map(|c| match c {
-                '.' => 0,
-                '#' => 1,
-                _ => panic!("Shouldn't happen."),
-            })
-        })
-        .collect::<Vec<usize>>();
-
-    let arr_size = (input.len() as f32).sqrt() as usize;
-
-    let mut res = Array3::<usize>::zeros((1, arr_size, arr_size));
-    let mut slice = res.slice_mut(s![0, 0..arr_size, 0..arr_size]);
+use lazy_static::lazy_static;
+use regex::Regex;
+use simple_error::*;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+// Sparse solution: only active cubes are stored, so the grid never has to grow,
+// and the same code runs in any number of dimensions.
+// Coordinates are [x, y, z, w, ...]; everything past y is an "extra" dimension
+
+lazy_static! {
+    static ref LAYER: Regex = Regex::new(r"^([a-z][a-z0-9]*)=(-?\d+)$").unwrap();
+}
 
-    let input = Array2::<usize>::from_shape_vec((arr_size, arr_size), input).unwrap();
+// Name of coordinate k in the layered format
+fn axis_name(k: usize) -> String {
+    match k {
+        2 => String::from("z"),
+        3 => String::from("w"),
+        _ => format!("d{}", k),
+    }
+}
 
-    slice.assign(&input);
-    res
+// Active cubes of a starting state, each with as many coordinates as its format gave.
+// Three formats are read:
+// - a single slice of . and #, of any width and height
+// - layers of such slices, each after a "z=0, w=0" style header and separated by blank lines
+// - one cube per line as comma separated coordinates: x,y[,z[,w...]]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Seed {
+    pub cells: Vec<Vec<i32>>,
 }
 
-#[aoc_generator(day17, part2)]
-pub fn input_generator2(input: &str) -> Array4<usize> {
-    let input = input
-        .lines()
-        .flat_map(|s| {
-            s.chars().map(|c| match c {
-                '.' => 0,
-                '#' => 1,
-                _ => panic!("Shouldn't happen."),
-            })
-        })
-        .collect::<Vec<usize>>();
+impl Seed {
+    fn parse_slice<'a>(
+        lines: impl Iterator<Item = (usize, &'a str)>,
+        extra: &[i32],
+    ) -> Result<Vec<Vec<i32>>, Box<dyn Error>> {
+        let mut res = vec![];
+        for (y, (n, l)) in lines.enumerate() {
+            for (x, c) in l.chars().enumerate() {
+                match c {
+                    '.' => {}
+                    '#' => {
+                        let mut cell = vec![x as i32, y as i32];
+                        cell.extend_from_slice(extra);
+                        res.push(cell);
+                    }
+                    _ => bail!("line {}: unexpected character {:?} in slice", n + 1, c),
+                }
+            }
+        }
+        Ok(res)
+    }
 
-    let arr_size = (input.len() as f32).sqrt() as usize;
+    fn parse_header(n: usize, l: &str) -> Result<Vec<i32>, Box<dyn Error>> {
+        l.split(", ")
+            .enumerate()
+            .map(|(k, part)| {
+                let cap = match LAYER.captures(part) {
+                    Some(cap) => cap,
+                    None => bail!("line {}: bad layer header {:?}", n + 1, l),
+                };
+                if cap[1] != axis_name(k + 2) {
+                    bail!(
+                        "line {}: expected {} in layer header, got {}",
+                        n + 1,
+                        axis_name(k + 2),
+                        &cap[1]
+                    );
+                }
+                Ok(cap[2].parse::<i32>()?)
+            })
+            .collect()
+    }
 
-    let mut res = Array4::<usize>::zeros((1, 1, arr_size, arr_size));
-    let mut slice = res.slice_mut(s![0, 0, 0..arr_size, 0..arr_size]);
+    fn parse_layers(input: &str) -> Result<Vec<Vec<i32>>, Box<dyn Error>> {
+        let lines = input.lines().enumerate().collect::<Vec<(usize, &str)>>();
+        let mut res = vec![];
+        let mut depth = None;
+        for block in lines.split(|(_, l)| l.trim().is_empty()) {
+            let (n, header) = match block.first() {
+                Some(&first) => first,
+                None => continue,
+            };
+            let extra = Seed::parse_header(n, header)?;
+            if *depth.get_or_insert(extra.len()) != extra.len() {
+                bail!(
+                    "line {}: layers disagree on the number of dimensions",
+                    n + 1
+                );
+            }
+            res.extend(Seed::parse_slice(block[1..].iter().copied(), &extra)?);
+        }
+        Ok(res)
+    }
 
-    let input = Array2::<usize>::from_shape_vec((arr_size, arr_size), input).unwrap();
+    fn parse_coordinates(input: &str) -> Result<Vec<Vec<i32>>, Box<dyn Error>> {
+        input
+            .lines()
+            .enumerate()
+            .filter(|(_, l)| !l.trim().is_empty())
+            .map(|(n, l)| {
+                let cell = l
+                    .split(',')
+                    .map(|v| v.trim().parse::<i32>())
+                    .collect::<Result<Vec<i32>, _>>()
+                    .map_err(|e| format!("line {}: bad coordinate in {:?}: {}", n + 1, l, e))?;
+                if cell.len() < 2 {
+                    bail!("line {}: need at least x and y: {:?}", n + 1, l);
+                }
+                Ok(cell)
+            })
+            .collect()
+    }
 
-    slice.assign(&input);
-    res
+    // The cubes as D dimensional cells. Missing coordinates are 0; extra ones must be 0 too
+    pub fn cells<const D: usize>(&self) -> Result<Vec<[i32; D]>, Box<dyn Error>> {
+        self.cells
+            .iter()
+            .map(|c| {
+                if c[D.min(c.len())..].iter().any(|&v| v != 0) {
+                    bail!("cube {:?} does not fit in {} dimensions", c, D);
+                }
+                let mut cell = [0; D];
+                cell.iter_mut().zip(c.iter()).for_each(|(d, &v)| *d = v);
+                Ok(cell)
+            })
+            .collect()
+    }
 }
 
-pub fn get_cell_value(input: &Array3<usize>, (z, y, x): (usize, usize, usize)) -> usize {
-    // Idea:
-    // 1. sum the values of all cells in a cube (x-1, x, x+1; y-1, y, y+1; z-1, z, z+1) and deduct the value of cell at (x, y, z)
-    //   - assume that if out of bounds, then the value is 0
-    let zmin = if z <= 1 { 0 } else { z - 1 };
-    let ymin = if y <= 1 { 0 } else { y - 1 };
-    let xmin = if x <= 1 { 0 } else { x - 1 };
-    let zmax = if z + 1 >= input.dim().0 {
-        input.dim().0 - 1
-    } else {
-        z + 1
-    };
-    let ymax = if y + 1 >= input.dim().1 {
-        input.dim().1 - 1
-    } else {
-        y + 1
-    };
-    let xmax = if x + 1 >= input.dim().2 {
-        input.dim().2 - 1
-    } else {
-        x + 1
-    };
-
-    let isl = input.slice(s![zmin..=zmax, ymin..=ymax, xmin..=xmax]);
-    let mut active =
-        if (zmin..=zmax).contains(&z) && (ymin..=ymax).contains(&y) && (xmin..=xmax).contains(&x) {
-            input[(z, y, x)]
+impl FromStr for Seed {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let first = s.lines().find(|l| !l.trim().is_empty()).unwrap_or("");
+        let cells = if first.contains('=') {
+            Seed::parse_layers(s)?
+        } else if first.contains(',') {
+            Seed::parse_coordinates(s)?
         } else {
-            0
+            Seed::parse_slice(s.lines().enumerate(), &[])?
         };
-    let sum = isl.sum() - active;
+        Ok(Seed { cells })
+    }
+}
 
-    // 2. apply the game rules
-    if active == 1 {
-        if sum < 2 || sum > 3 {
-            active = 0;
+#[aoc_generator(day17)]
+pub fn input_generator(input: &str) -> Result<Seed, Box<dyn Error>> {
+    input.parse::<Seed>()
+}
+
+// All 3^D - 1 steps to a neighbouring cube
+fn neighbour_offsets<const D: usize>() -> Vec<[i32; D]> {
+    (0..3usize.pow(D as u32))
+        .map(|mut n| {
+            let mut off = [0; D];
+            off.iter_mut().for_each(|o| {
+                *o = (n % 3) as i32 - 1;
+                n /= 3;
+            });
+            off
+        })
+        .filter(|off| off.iter().any(|&o| o != 0))
+        .collect()
+}
+
+// Flipping the sign of any extra coordinate of any active cube gives an active cube
+fn is_mirror_symmetric<const D: usize>(cells: &HashSet<[i32; D]>) -> bool {
+    cells.iter().all(|c| {
+        (2..D).all(|k| {
+            let mut m = *c;
+            m[k] = -m[k];
+            cells.contains(&m)
+        })
+    })
+}
+
+// The set of active cubes.
+// A state symmetric under w -> -w (and likewise for every extra dimension) stays that way,
+// so when the start is symmetric only cubes with non-negative extra coordinates are kept:
+// the folded state is 2^(D-2) times smaller and so is the work per cycle
+#[derive(Debug, Clone, PartialEq)]
+pub struct PocketDimension<const D: usize> {
+    active: HashSet<[i32; D]>,
+    folded: bool,
+}
+
+impl<const D: usize> PocketDimension<D> {
+    // Folds the state if its symmetry allows it
+    pub fn new(cells: impl IntoIterator<Item = [i32; D]>) -> PocketDimension<D> {
+        let active = cells.into_iter().collect::<HashSet<[i32; D]>>();
+        if !is_mirror_symmetric(&active) {
+            return PocketDimension {
+                active,
+                folded: false,
+            };
         }
-    } else {
-        if sum == 3 {
-            active = 1;
+        PocketDimension {
+            active: active
+                .into_iter()
+                .filter(|c| c[2..].iter().all(|&v| v >= 0))
+                .collect(),
+            folded: true,
         }
     }
-    // 3. return the value
-    active
-}
 
-pub fn step(orig: &Array3<usize>) -> Array3<usize> {
-    // Idea:
-    // 1. clone orig array into 1 larger one, by 2 on each dimension, 1 on each side, called dest
-    // 2. for each cell in dest, get the cell value using orig
-    // 3. write the cell value in dest
-    // 4. return dest
-    let d = orig.dim();
-    //eprintln!("dim: {:?}", d);
-    let mut ocl = Array3::<usize>::zeros((d.0 + 2, d.1 + 2, d.2 + 2));
-    let mut oclsl = ocl.slice_mut(s![1..=d.0, 1..=d.1, 1..=d.2]);
-    oclsl.assign(orig);
-    let orig = ocl.clone();
-    //eprintln!("ocl: {:?}", ocl);
-    for ((z, y, x), val) in ocl.indexed_iter_mut() {
-        *val = get_cell_value(&orig, (z, y, x));
-    }
-
-    ocl
-}
+    // Never folds, for checking the folded run against
+    pub fn unfolded(cells: impl IntoIterator<Item = [i32; D]>) -> PocketDimension<D> {
+        PocketDimension {
+            active: cells.into_iter().collect(),
+            folded: false,
+        }
+    }
 
-#[aoc(day17, part1)]
-pub fn part1(input: &Array3<usize>) -> usize {
-    let mut t = input.clone();
+    pub fn is_folded(&self) -> bool {
+        self.folded
+    }
+
+    // Every active cube, mirror images included, in sorted order
+    pub fn cells(&self) -> Vec<[i32; D]> {
+        let mut res = if self.folded {
+            self.active
+                .iter()
+                .flat_map(|&c| {
+                    (2..D).fold(vec![c], |acc, k| {
+                        acc.into_iter()
+                            .flat_map(|m| {
+                                let mut f = m;
+                                f[k] = -f[k];
+                                if f == m {
+                                    vec![m]
+                                } else {
+                                    vec![m, f]
+                                }
+                            })
+                            .collect()
+                    })
+                })
+                .collect::<Vec<[i32; D]>>()
+        } else {
+            self.active.iter().copied().collect()
+        };
+        res.sort_unstable();
+        res
+    }
 
-    for _i in 0..6 {
-        //eprintln!("iteration: {} array: {:?}", i, t);
-        t = step(&t);
+    pub fn population(&self) -> usize {
+        if !self.folded {
+            return self.active.len();
+        }
+        self.active
+            .iter()
+            .map(|c| 1 << c[2..].iter().filter(|&&v| v != 0).count())
+            .sum()
     }
 
-    //eprintln!("FINAL array: {:?}", t);
+    // Each active cube adds itself to its neighbours' counts.
+    // When folded, a target at 0 in some extra dimension also sees the mirror image of a cube
+    // at 1 there, so that cube counts twice for it
+    pub fn step(&self) -> PocketDimension<D> {
+        let offsets = neighbour_offsets::<D>();
+        let mut counts: HashMap<[i32; D], u32> = HashMap::new();
+        self.active.iter().for_each(|c| {
+            offsets.iter().for_each(|off| {
+                let mut t = *c;
+                t.iter_mut().zip(off.iter()).for_each(|(v, o)| *v += o);
+                let mut weight = 1;
+                if self.folded {
+                    if t[2..].iter().any(|&v| v < 0) {
+                        return;
+                    }
+                    weight = (2..D)
+                        .filter(|&k| c[k] == 1 && t[k] == 0)
+                        .fold(1, |w, _| w * 2);
+                }
+                *counts.entry(t).or_insert(0) += weight;
+            })
+        });
+        PocketDimension {
+            active: counts
+                .into_iter()
+                .filter(|(t, n)| *n == 3 || (*n == 2 && self.active.contains(t)))
+                .map(|(t, _)| t)
+                .collect(),
+            folded: self.folded,
+        }
+    }
 
-    t.sum()
+    pub fn run(&self, cycles: usize) -> PocketDimension<D> {
+        (0..cycles).fold(self.clone(), |acc, _| acc.step())
+    }
 }
 
-pub fn get_cell_value2(input: &Array4<usize>, (w, z, y, x): (usize, usize, usize, usize)) -> usize {
-    // Idea:
-    // 1. sum the values of all cells in a cube (x-1, x, x+1; y-1, y, y+1; z-1, z, z+1) and deduct the value of cell at (x, y, z)
-    //   - assume that if out of bounds, then the value is 0
-    let wmin = if w <= 1 { 0 } else { w - 1 };
-    let zmin = if z <= 1 { 0 } else { z - 1 };
-    let ymin = if y <= 1 { 0 } else { y - 1 };
-    let xmin = if x <= 1 { 0 } else { x - 1 };
-    let wmax = if w + 1 >= input.dim().0 {
-        input.dim().0 - 1
-    } else {
-        w + 1
-    };
-    let zmax = if z + 1 >= input.dim().1 {
-        input.dim().1 - 1
-    } else {
-        z + 1
-    };
-    let ymax = if y + 1 >= input.dim().2 {
-        input.dim().2 - 1
-    } else {
-        y + 1
-    };
-    let xmax = if x + 1 >= input.dim().3 {
-        input.dim().3 - 1
-    } else {
-        x + 1
-    };
-
-    let isl = input.slice(s![wmin..=wmax, zmin..=zmax, ymin..=ymax, xmin..=xmax]);
-    let mut active = if (wmin..=wmax).contains(&w)
-        && (zmin..=zmax).contains(&z)
-        && (ymin..=ymax).contains(&y)
-        && (xmin..=xmax).contains(&x)
-    {
-        input[(w, z, y, x)]
-    } else {
-        0
-    };
-    let sum = isl.sum() - active;
-
-    // 2. apply the game rules
-    if active == 1 {
-        if sum < 2 || sum > 3 {
-            active = 0;
+// The layered format, with every layer cut to the same x and y bounds
+impl<const D: usize> fmt::Display for PocketDimension<D> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let cells = self.cells();
+        if cells.is_empty() {
+            return Ok(());
         }
-    } else {
-        if sum == 3 {
-            active = 1;
+        let (xmin, xmax) = (
+            cells.iter().map(|c| c[0]).min().unwrap(),
+            cells.iter().map(|c| c[0]).max().unwrap(),
+        );
+        let (ymin, ymax) = (
+            cells.iter().map(|c| c[1]).min().unwrap(),
+            cells.iter().map(|c| c[1]).max().unwrap(),
+        );
+        // Layers go z first, then w and so on, the way the puzzle lists them
+        let mut layers: BTreeMap<Vec<i32>, HashSet<(i32, i32)>> = BTreeMap::new();
+        cells.iter().for_each(|c| {
+            let key = c[2..].iter().rev().copied().collect::<Vec<i32>>();
+            layers.entry(key).or_default().insert((c[0], c[1]));
+        });
+        for (i, (key, layer)) in layers.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            if D > 2 {
+                let header = key
+                    .iter()
+                    .rev()
+                    .enumerate()
+                    .map(|(k, v)| format!("{}={}", axis_name(k + 2), v))
+                    .collect::<Vec<String>>();
+                writeln!(f, "{}", header.join(", "))?;
+            }
+            for y in ymin..=ymax {
+                let row = (xmin..=xmax)
+                    .map(|x| if layer.contains(&(x, y)) { '#' } else { '.' })
+                    .collect::<String>();
+                writeln!(f, "{}", row)?;
+            }
         }
+        Ok(())
     }
-    // 3. return the value
-    active
 }
 
-pub fn step2(orig: &Array4<usize>) -> Array4<usize> {
-    // Idea:
-    // 1. clone orig array into 1 larger one, by 2 on each dimension, 1 on each side, called dest
-    // 2. for each cell in dest, get the cell value using orig
-    // 3. write the cell value in dest
-    // 4. return dest
-    let d = orig.dim();
-    //eprintln!("dim: {:?}", d);
-    let mut ocl = Array4::<usize>::zeros((d.0 + 2, d.1 + 2, d.2 + 2, d.3 + 2));
-    let mut oclsl = ocl.slice_mut(s![1..=d.0, 1..=d.1, 1..=d.2, 1..=d.3]);
-    oclsl.assign(orig);
-    let orig = ocl.clone();
-    //eprintln!("ocl: {:?}", ocl);
-    for ((w, z, y, x), val) in ocl.indexed_iter_mut() {
-        *val = get_cell_value2(&orig, (w, z, y, x));
-    }
-
-    ocl
+pub fn boot<const D: usize>(seed: &Seed, cycles: usize) -> Result<usize, Box<dyn Error>> {
+    Ok(PocketDimension::<D>::new(seed.cells::<D>()?)
+        .run(cycles)
+        .population())
 }
 
-#[aoc(day17, part2)]
-pub fn part2(input: &Array4<usize>) -> usize {
-    let mut t = input.clone();
-
-    for _i in 0..6 {
-        //eprintln!("iteration: {} array: {:?}", i, t);
-        t = step2(&t);
-    }
-
-    //eprintln!("FINAL array: {:?}", t);
+#[aoc(day17, part1)]
+pub fn part1(input: &Seed) -> Result<usize, Box<dyn Error>> {
+    boot::<3>(input, 6)
+}
 
-    t.sum()
+#[aoc(day17, part2)]
+pub fn part2(input: &Seed) -> Result<usize, Box<dyn Error>> {
+    boot::<4>(input, 6)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use ndarray::arr3;
 
     const INPUT: &str = ".#.
 ..#
@@ -234,22 +368,159 @@ mod tests {
     #[test]
     pub fn test_generator() {
         assert_eq!(
-            input_generator(INPUT),
-            arr3(&[
-                [[0, 0, 0], [0, 0, 0], [0, 0, 0]],
-                [[0, 1, 0], [0, 0, 1], [1, 1, 1]],
-                [[0, 0, 0], [0, 0, 0], [0, 0, 0]]
-            ])
+            input_generator(INPUT).unwrap().cells::<2>().unwrap(),
+            vec![[1, 0], [2, 1], [0, 2], [1, 2], [2, 2]]
+        );
+        let err = input_generator(".#\n#?").unwrap_err();
+        assert_eq!(err.to_string(), "line 2: unexpected character '?' in slice");
+    }
+
+    #[test]
+    pub fn test_seed_formats() {
+        // Rectangular slices keep their shape
+        let wide = input_generator("#....#\n.#..#.").unwrap();
+        assert_eq!(
+            wide.cells::<3>().unwrap(),
+            vec![[0, 0, 0], [5, 0, 0], [1, 1, 0], [4, 1, 0]]
+        );
+
+        let layers = input_generator(
+            "z=-1, w=0
+#.
+.#
+
+z=2, w=3
+##",
+        )
+        .unwrap();
+        assert_eq!(
+            layers.cells::<4>().unwrap(),
+            vec![[0, 0, -1, 0], [1, 1, -1, 0], [0, 0, 2, 3], [1, 0, 2, 3]]
+        );
+        assert!(layers.cells::<3>().is_err());
+        assert!(!PocketDimension::<4>::new(layers.cells::<4>().unwrap()).is_folded());
+
+        let coords = input_generator("0,0\n1,2,-3\n-4,5,6,7").unwrap();
+        assert_eq!(
+            coords.cells::<4>().unwrap(),
+            vec![[0, 0, 0, 0], [1, 2, -3, 0], [-4, 5, 6, 7]]
+        );
+
+        let err = |s: &str| input_generator(s).unwrap_err().to_string();
+        assert_eq!(
+            err("z=0\n#.\n\nw=1\n.#"),
+            "line 4: expected z in layer header, got w"
+        );
+        assert_eq!(
+            err("z=0\n#.\n\nz=1, w=1\n.#"),
+            "line 4: layers disagree on the number of dimensions"
         );
+        assert_eq!(err("z=zero\n#."), "line 1: bad layer header \"z=zero\"");
+        assert!(err("1,2\n3").starts_with("line 2: need at least x and y"));
+        assert!(err("1,2\n3,y").starts_with("line 2: bad coordinate"));
+    }
+
+    #[test]
+    pub fn test_display() {
+        let once =
+            PocketDimension::<3>::new(input_generator(INPUT).unwrap().cells::<3>().unwrap()).step();
+        assert_eq!(
+            once.to_string(),
+            "z=-1
+#..
+..#
+.#.
+
+z=0
+#.#
+.##
+.#.
+
+z=1
+#..
+..#
+.#.
+"
+        );
+
+        // What gets printed reads back in as the same state, moved to start at 0, 0
+        let twice =
+            PocketDimension::<4>::new(input_generator(INPUT).unwrap().cells::<4>().unwrap()).run(2);
+        let text = twice.to_string();
+        assert!(text.starts_with("z=-2, w=-2\n"));
+        let back = PocketDimension::<4>::new(input_generator(&text).unwrap().cells::<4>().unwrap());
+        assert_eq!(back.to_string(), text);
+        assert_eq!(back.population(), twice.population());
     }
 
     #[test]
     pub fn test_part1() {
-        assert_eq!(part1(&input_generator(INPUT)), 112);
+        assert_eq!(part1(&input_generator(INPUT).unwrap()).unwrap(), 112);
     }
 
     #[test]
     pub fn test_part2() {
-        assert_eq!(part2(&input_generator2(INPUT)), 848);
+        assert_eq!(part2(&input_generator(INPUT).unwrap()).unwrap(), 848);
+    }
+
+    #[test]
+    pub fn test_step() {
+        let seed = input_generator(INPUT).unwrap();
+        let once = PocketDimension::<3>::new(seed.cells::<3>().unwrap()).step();
+        assert!(once.is_folded());
+        assert_eq!(once.population(), 11);
+        assert_eq!(
+            once.cells()
+                .into_iter()
+                .filter(|c| c[2] == -1)
+                .collect::<Vec<[i32; 3]>>(),
+            vec![[0, 1, -1], [1, 3, -1], [2, 2, -1]]
+        );
+        assert_eq!(once.cells().len(), 11);
+    }
+
+    #[test]
+    pub fn test_folding() {
+        let seed = input_generator(INPUT).unwrap();
+        let folded = PocketDimension::<5>::new(seed.cells::<5>().unwrap()).run(3);
+        let unfolded = PocketDimension::<5>::unfolded(seed.cells::<5>().unwrap()).run(3);
+        assert!(folded.is_folded() && !unfolded.is_folded());
+        assert_eq!(folded.population(), unfolded.population());
+        assert_eq!(folded.cells(), unfolded.cells());
+
+        // Not symmetric in z, so nothing gets folded
+        let lopsided = PocketDimension::<3>::new(vec![[0, 0, 1], [1, 0, 1], [2, 0, 1]]);
+        assert!(!lopsided.is_folded());
+        assert_eq!(lopsided.step().population(), 9);
+
+        assert_eq!(boot::<5>(&seed, 6).unwrap(), 5760);
+    }
+
+    #[test]
+    pub fn test_six_dimensions() {
+        // In 6D a cube has 728 neighbours, and folding doubles counts once per extra dimension,
+        // far more than a u8 holds
+        let seed = input_generator(INPUT).unwrap();
+        assert_eq!(boot::<6>(&seed, 6).unwrap(), 35936);
+
+        // Every cube of a full 3^6 block is crowded out; only the 6 * 2^5 cubes two steps out
+        // in five dimensions and level with the centre in the sixth see exactly 3 neighbours
+        let dense = (0..729)
+            .map(|i| {
+                let mut c = [0; 6];
+                c.iter_mut().fold(i, |rest, v| {
+                    *v = rest % 3 - 1;
+                    rest / 3
+                });
+                c
+            })
+            .collect::<Vec<[i32; 6]>>();
+        let folded = PocketDimension::<6>::new(dense.clone()).step();
+        assert!(folded.is_folded());
+        assert_eq!(folded.population(), 192);
+        assert_eq!(
+            folded.cells(),
+            PocketDimension::<6>::unfolded(dense).step().cells()
+        );
     }
 }
@@ -1,92 +1,698 @@
-use aoc_runner_derive::aoc;
-use lazy_static::lazy_static;
+use aoc_runner_derive::{aoc, aoc_generator};
+use pest::error::{ErrorVariant, LineColLocation};
 use pest::iterators::{Pair, Pairs};
-use pest::prec_climber::{Assoc, Operator, PrecClimber};
 use pest::Parser;
 use pest_derive::*;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
 
 // From here: https://pest.rs/book/intro.html
 #[derive(Parser)]
 #[grammar = "day18.pest"]
 pub struct CalcParser;
 
-lazy_static! {
-    static ref PREC_CLIMBER_P1: PrecClimber<Rule> = {
-        use Assoc::*;
-        use Rule::*;
-
-        PrecClimber::new(vec![
-            Operator::new(add, Left)
-                | Operator::new(subtract, Left)
-                | Operator::new(multiply, Left)
-                | Operator::new(divide, Left),
-            Operator::new(power, Right),
-        ])
-    };
-    static ref PREC_CLIMBER_P2: PrecClimber<Rule> = {
-        use Assoc::*;
-        use Rule::*;
-
-        PrecClimber::new(vec![
-            Operator::new(multiply, Left) | Operator::new(divide, Left),
-            Operator::new(add, Left) | Operator::new(subtract, Left),
-            Operator::new(power, Right),
-        ])
-    };
-}
-
-fn eval(pc: &PrecClimber<Rule>, expression: Pairs<Rule>) -> f64 {
-    pc.climb(
-        expression,
-        |pair: Pair<Rule>| match pair.as_rule() {
-            Rule::num => pair.as_str().parse::<f64>().unwrap(),
-            Rule::expr => eval(pc, pair.into_inner()),
-            _ => unreachable!(),
-        },
-        |lhs: f64, op: Pair<Rule>, rhs: f64| match op.as_rule() {
-            Rule::add => lhs + rhs,
-            Rule::subtract => lhs - rhs,
-            Rule::multiply => lhs * rhs,
-            Rule::divide => lhs / rhs,
-            Rule::power => lhs.powf(rhs),
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+}
+
+impl BinOp {
+    fn from_rule(rule: Rule) -> Option<BinOp> {
+        match rule {
+            Rule::add => Some(BinOp::Add),
+            Rule::subtract => Some(BinOp::Sub),
+            Rule::multiply => Some(BinOp::Mul),
+            Rule::divide => Some(BinOp::Div),
+            Rule::power => Some(BinOp::Pow),
+            _ => None,
+        }
+    }
+
+    // Exact integer arithmetic: anything that does not fit an i128 is an error, not a wrap
+    pub fn apply(self, lhs: i128, rhs: i128) -> Result<i128, EvalError> {
+        let res = match self {
+            BinOp::Add => lhs.checked_add(rhs),
+            BinOp::Sub => lhs.checked_sub(rhs),
+            BinOp::Mul => lhs.checked_mul(rhs),
+            BinOp::Div => {
+                if rhs == 0 {
+                    return Err(EvalError::DivisionByZero);
+                }
+                lhs.checked_div(rhs)
+            }
+            BinOp::Pow => {
+                if rhs < 0 {
+                    return Err(EvalError::NegativeExponent(rhs));
+                }
+                u32::try_from(rhs).ok().and_then(|e| lhs.checked_pow(e))
+            }
+        };
+        res.ok_or(EvalError::Overflow(self, lhs, rhs))
+    }
+}
+
+impl fmt::Display for BinOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let c = match self {
+            BinOp::Add => '+',
+            BinOp::Sub => '-',
+            BinOp::Mul => '*',
+            BinOp::Div => '/',
+            BinOp::Pow => '^',
+        };
+        write!(f, "{}", c)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EvalError {
+    Overflow(BinOp, i128, i128),
+    DivisionByZero,
+    NegativeExponent(i128),
+    // The operator table has no entry for this operator
+    UnknownOperator(BinOp),
+    UnknownVariable(String),
+    UnknownFunction(String),
+    Arity { func: Func, got: usize },
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EvalError::Overflow(op, lhs, rhs) => write!(f, "{} {} {} overflows", lhs, op, rhs),
+            EvalError::DivisionByZero => write!(f, "division by zero"),
+            EvalError::NegativeExponent(e) => write!(f, "negative exponent {}", e),
+            EvalError::UnknownOperator(op) => write!(f, "no precedence given for {}", op),
+            EvalError::UnknownVariable(name) => write!(f, "unknown variable {}", name),
+            EvalError::UnknownFunction(name) => write!(f, "unknown function {}", name),
+            EvalError::Arity { func, got } => write!(
+                f,
+                "{} takes {} argument(s), got {}",
+                func,
+                func.arity(),
+                got
+            ),
+        }
+    }
+}
+
+impl Error for EvalError {}
+
+fn negate(n: i128) -> Result<i128, EvalError> {
+    n.checked_neg().ok_or(EvalError::Overflow(BinOp::Sub, 0, n))
+}
+
+// Built-in functions; min and max take any number of arguments
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Func {
+    Min,
+    Max,
+    Abs,
+    Mod,
+}
+
+impl Func {
+    pub fn lookup(name: &str) -> Option<Func> {
+        match name {
+            "min" => Some(Func::Min),
+            "max" => Some(Func::Max),
+            "abs" => Some(Func::Abs),
+            "mod" => Some(Func::Mod),
+            _ => None,
+        }
+    }
+
+    fn arity(self) -> &'static str {
+        match self {
+            Func::Min | Func::Max => "at least 1",
+            Func::Abs => "1",
+            Func::Mod => "2",
+        }
+    }
+
+    fn check_arity(self, got: usize) -> Result<(), EvalError> {
+        let ok = match self {
+            Func::Min | Func::Max => got >= 1,
+            Func::Abs => got == 1,
+            Func::Mod => got == 2,
+        };
+        if ok {
+            Ok(())
+        } else {
+            Err(EvalError::Arity { func: self, got })
+        }
+    }
+
+    // mod always gives a result between 0 and |divisor|, whatever the signs
+    pub fn apply(self, args: &[i128]) -> Result<i128, EvalError> {
+        self.check_arity(args.len())?;
+        match self {
+            Func::Min => Ok(*args.iter().min().unwrap()),
+            Func::Max => Ok(*args.iter().max().unwrap()),
+            Func::Abs => {
+                if args[0] < 0 {
+                    negate(args[0])
+                } else {
+                    Ok(args[0])
+                }
+            }
+            Func::Mod => {
+                if args[1] == 0 {
+                    return Err(EvalError::DivisionByZero);
+                }
+                args[0]
+                    .checked_rem_euclid(args[1])
+                    .ok_or(EvalError::Overflow(BinOp::Div, args[0], args[1]))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Func {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Func::Min => "min",
+            Func::Max => "max",
+            Func::Abs => "abs",
+            Func::Mod => "mod",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Assoc {
+    Left,
+    Right,
+}
+
+// Precedence and associativity of each operator; a higher level binds tighter
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OperatorTable {
+    ops: HashMap<BinOp, (u8, Assoc)>,
+}
+
+impl OperatorTable {
+    pub fn new() -> OperatorTable {
+        OperatorTable::default()
+    }
+
+    pub fn with(mut self, op: BinOp, level: u8, assoc: Assoc) -> OperatorTable {
+        self.ops.insert(op, (level, assoc));
+        self
+    }
+
+    pub fn get(&self, op: BinOp) -> Option<(u8, Assoc)> {
+        self.ops.get(&op).copied()
+    }
+
+    // Everything but ^ on one level, left to right
+    pub fn part1() -> OperatorTable {
+        OperatorTable::new()
+            .with(BinOp::Add, 1, Assoc::Left)
+            .with(BinOp::Sub, 1, Assoc::Left)
+            .with(BinOp::Mul, 1, Assoc::Left)
+            .with(BinOp::Div, 1, Assoc::Left)
+            .with(BinOp::Pow, 2, Assoc::Right)
+    }
+
+    // Addition before multiplication
+    pub fn part2() -> OperatorTable {
+        OperatorTable::new()
+            .with(BinOp::Mul, 1, Assoc::Left)
+            .with(BinOp::Div, 1, Assoc::Left)
+            .with(BinOp::Add, 2, Assoc::Left)
+            .with(BinOp::Sub, 2, Assoc::Left)
+            .with(BinOp::Pow, 3, Assoc::Right)
+    }
+
+    // Multiplication before addition, for comparison
+    // Not quite school rules: like in every table, a minus sign in front of an operand binds
+    // tighter than any operator, so -2 ^ 2 is 4
+    pub fn standard() -> OperatorTable {
+        OperatorTable::new()
+            .with(BinOp::Add, 1, Assoc::Left)
+            .with(BinOp::Sub, 1, Assoc::Left)
+            .with(BinOp::Mul, 2, Assoc::Left)
+            .with(BinOp::Div, 2, Assoc::Left)
+            .with(BinOp::Pow, 3, Assoc::Right)
+    }
+}
+
+// A syntax problem at a 1-based line and column of the input
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.line, self.column, self.message
+        )
+    }
+}
+
+impl Error for SyntaxError {}
+
+fn rule_name(rule: &Rule) -> &'static str {
+    match rule {
+        Rule::num | Rule::neg | Rule::call | Rule::expr => "operand",
+        Rule::ident => "name",
+        Rule::add | Rule::subtract | Rule::multiply | Rule::divide | Rule::power => "operator",
+        Rule::EOI => "end of line",
+        Rule::kw_let => "let",
+        _ => "something else",
+    }
+}
+
+impl From<pest::error::Error<Rule>> for SyntaxError {
+    fn from(e: pest::error::Error<Rule>) -> SyntaxError {
+        let (line, column) = match e.line_col {
+            LineColLocation::Pos(pos) => pos,
+            LineColLocation::Span(start, _) => start,
+        };
+        // A variable is an operand too, so "name" only shows up on its own, as after let
+        let names = |rules: &[Rule]| {
+            let mut names: Vec<&str> = vec![];
+            rules.iter().map(rule_name).for_each(|n| {
+                if !names.contains(&n) {
+                    names.push(n);
+                }
+            });
+            if names.contains(&"operand") {
+                names.retain(|&n| n != "name");
+            }
+            names.join(" or ")
+        };
+        let message = match &e.variant {
+            ErrorVariant::ParsingError { positives, .. } if !positives.is_empty() => {
+                format!("expected {}", names(positives))
+            }
+            ErrorVariant::ParsingError { negatives, .. } => {
+                format!("unexpected {}", names(negatives))
+            }
+            ErrorVariant::CustomError { message } => message.clone(),
+        };
+        SyntaxError {
+            line,
+            column,
+            message,
+        }
+    }
+}
+
+// The expression the way it was written: a run of terms joined by operators.
+// How it groups depends on the operator table, so this is what gets stored between parts
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chain {
+    pub terms: Vec<Term>,
+    pub ops: Vec<BinOp>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Term {
+    Num(i128),
+    Var(String),
+    // Applies to the single term after it, before any operator gets a look in
+    Neg(Box<Term>),
+    Call(String, Vec<Chain>),
+    Group(Chain),
+}
+
+impl Term {
+    fn from_pair(pair: Pair<Rule>) -> Result<Term, SyntaxError> {
+        match pair.as_rule() {
+            Rule::num => {
+                let n = pair.as_str().parse::<i128>().map_err(|_| {
+                    let (line, column) = pair.as_span().start_pos().line_col();
+                    SyntaxError {
+                        line,
+                        column,
+                        message: format!("number out of range: {}", pair.as_str()),
+                    }
+                })?;
+                Ok(Term::Num(n))
+            }
+            Rule::ident => Ok(Term::Var(String::from(pair.as_str()))),
+            Rule::neg => Ok(Term::Neg(Box::new(Term::from_pair(
+                pair.into_inner().next().unwrap(),
+            )?))),
+            Rule::call => {
+                let mut inner = pair.into_inner();
+                let name = String::from(inner.next().unwrap().as_str());
+                let args = inner
+                    .map(|p| Chain::from_pairs(p.into_inner()))
+                    .collect::<Result<Vec<Chain>, SyntaxError>>()?;
+                Ok(Term::Call(name, args))
+            }
+            Rule::expr => Ok(Term::Group(Chain::from_pairs(pair.into_inner())?)),
             _ => unreachable!(),
-        },
+        }
+    }
+}
+
+impl Chain {
+    fn from_pairs(pairs: Pairs<Rule>) -> Result<Chain, SyntaxError> {
+        let mut chain = Chain {
+            terms: vec![],
+            ops: vec![],
+        };
+        for pair in pairs {
+            match BinOp::from_rule(pair.as_rule()) {
+                Some(op) => chain.ops.push(op),
+                None if pair.as_rule() == Rule::EOI => {}
+                None => chain.terms.push(Term::from_pair(pair)?),
+            }
+        }
+        Ok(chain)
+    }
+
+    // Precedence climbing: fold in operators binding at least as tight as min_level, and let
+    // the recursive call take the right operand as far as the operator's own level allows.
+    // The operator after terms[i] is ops[i]
+    fn climb(
+        &self,
+        table: &OperatorTable,
+        i: &mut usize,
+        min_level: u16,
+    ) -> Result<Expr, EvalError> {
+        let mut lhs = self.terms[*i].to_expr(table)?;
+        while let Some(&op) = self.ops.get(*i) {
+            let (level, assoc) = table.get(op).ok_or(EvalError::UnknownOperator(op))?;
+            // u16, so a left-associative operator at level 255 can still ask for one above it
+            let level = u16::from(level);
+            if level < min_level {
+                break;
+            }
+            *i += 1;
+            let next_min = match assoc {
+                Assoc::Left => level + 1,
+                Assoc::Right => level,
+            };
+            let rhs = self.climb(table, i, next_min)?;
+            lhs = Expr::Bin(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    pub fn to_expr(&self, table: &OperatorTable) -> Result<Expr, EvalError> {
+        self.climb(table, &mut 0, 0)
+    }
+}
+
+impl Term {
+    fn to_expr(&self, table: &OperatorTable) -> Result<Expr, EvalError> {
+        match self {
+            Term::Num(n) => Ok(Expr::Num(*n)),
+            Term::Var(name) => Ok(Expr::Var(name.clone())),
+            Term::Neg(t) => Ok(Expr::Neg(Box::new(t.to_expr(table)?))),
+            Term::Call(name, args) => {
+                let func =
+                    Func::lookup(name).ok_or_else(|| EvalError::UnknownFunction(name.clone()))?;
+                func.check_arity(args.len())?;
+                Ok(Expr::Call(
+                    func,
+                    args.iter()
+                        .map(|a| a.to_expr(table))
+                        .collect::<Result<Vec<Expr>, EvalError>>()?,
+                ))
+            }
+            Term::Group(chain) => chain.to_expr(table),
+        }
+    }
+}
+
+impl fmt::Display for Term {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Term::Num(n) => write!(f, "{}", n),
+            Term::Var(name) => write!(f, "{}", name),
+            Term::Neg(t) => write!(f, "-{}", t),
+            Term::Call(name, args) => write!(f, "{}({})", name, join(args)),
+            Term::Group(chain) => write!(f, "({})", chain),
+        }
+    }
+}
+
+fn join<T: ToString>(items: &[T]) -> String {
+    items
+        .iter()
+        .map(|i| i.to_string())
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+// Shows the chain as written, parentheses and all
+impl fmt::Display for Chain {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, term) in self.terms.iter().enumerate() {
+            if i > 0 {
+                write!(f, " {} ", self.ops[i - 1])?;
+            }
+            write!(f, "{}", term)?;
+        }
+        Ok(())
+    }
+}
+
+pub fn parse_line(line: &str) -> Result<Chain, SyntaxError> {
+    Chain::from_pairs(
+        CalcParser::parse(Rule::calculation, line)?
+            .next()
+            .unwrap()
+            .into_inner(),
     )
 }
 
-//#[aoc_generator(day18)]
-// Commented this out because I didn't want to debug why "aoc" macro was requesting me to provide lifetime specifiers
-pub fn input_generator(input: &str) -> Vec<Pairs<Rule>> {
+// A line of the calculator: either an expression or "let name = expression"
+#[derive(Debug, Clone, PartialEq)]
+pub enum Statement {
+    Let(String, Chain),
+    Eval(Chain),
+}
+
+pub fn parse_statement(line: &str) -> Result<Statement, SyntaxError> {
+    let pair = CalcParser::parse(Rule::statement, line)?.next().unwrap();
+    match pair.as_rule() {
+        Rule::binding => {
+            let mut inner = pair.into_inner().skip(1);
+            let name = String::from(inner.next().unwrap().as_str());
+            let chain = Chain::from_pairs(inner.next().unwrap().into_inner())?;
+            Ok(Statement::Let(name, chain))
+        }
+        _ => Ok(Statement::Eval(Chain::from_pairs(pair.into_inner())?)),
+    }
+}
+
+pub type Env = HashMap<String, i128>;
+
+// The expression grouped by a particular operator table
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Num(i128),
+    Var(String),
+    Neg(Box<Expr>),
+    Call(Func, Vec<Expr>),
+    Bin(BinOp, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    pub fn eval(&self) -> Result<i128, EvalError> {
+        match self {
+            Expr::Num(n) => Ok(*n),
+            Expr::Var(name) => Err(EvalError::UnknownVariable(name.clone())),
+            Expr::Neg(e) => negate(e.eval()?),
+            Expr::Call(func, args) => func.apply(
+                &args
+                    .iter()
+                    .map(|a| a.eval())
+                    .collect::<Result<Vec<i128>, EvalError>>()?,
+            ),
+            Expr::Bin(op, lhs, rhs) => op.apply(lhs.eval()?, rhs.eval()?),
+        }
+    }
+
+    // Replaces every bound variable with its value; unbound ones stay and fail on eval
+    pub fn substitute(&self, env: &Env) -> Expr {
+        match self {
+            Expr::Var(name) => env
+                .get(name)
+                .map_or_else(|| self.clone(), |&v| Expr::Num(v)),
+            Expr::Num(_) => self.clone(),
+            Expr::Neg(e) => Expr::Neg(Box::new(e.substitute(env))),
+            Expr::Call(func, args) => {
+                Expr::Call(*func, args.iter().map(|a| a.substitute(env)).collect())
+            }
+            Expr::Bin(op, lhs, rhs) => Expr::Bin(
+                *op,
+                Box::new(lhs.substitute(env)),
+                Box::new(rhs.substitute(env)),
+            ),
+        }
+    }
+
+    // Anything but a plain number or name after a minus sign goes in parentheses
+    fn render_neg(e: &Expr, inner: String) -> String {
+        match e {
+            Expr::Num(n) if *n >= 0 => format!("-{}", inner),
+            Expr::Var(_) | Expr::Call(..) => format!("-{}", inner),
+            _ => format!("-({})", inner),
+        }
+    }
+
+    // Every operation in parentheses, so the grouping is plain to see
+    pub fn explicit(&self) -> String {
+        match self {
+            Expr::Num(n) => n.to_string(),
+            Expr::Var(name) => name.clone(),
+            Expr::Neg(e) => Expr::render_neg(e, e.explicit()),
+            Expr::Call(func, args) => format!(
+                "{}({})",
+                func,
+                join(&args.iter().map(|a| a.explicit()).collect::<Vec<String>>())
+            ),
+            Expr::Bin(op, lhs, rhs) => {
+                let side = |e: &Expr| match e {
+                    Expr::Bin(..) => format!("({})", e.explicit()),
+                    _ => e.explicit(),
+                };
+                format!("{} {} {}", side(lhs), op, side(rhs))
+            }
+        }
+    }
+
+    // Only the parentheses the table needs to read the expression back the same way
+    pub fn render(&self, table: &OperatorTable) -> String {
+        match self {
+            Expr::Num(n) => n.to_string(),
+            Expr::Var(name) => name.clone(),
+            Expr::Neg(e) => Expr::render_neg(e, e.render(table)),
+            Expr::Call(func, args) => format!(
+                "{}({})",
+                func,
+                join(
+                    &args
+                        .iter()
+                        .map(|a| a.render(table))
+                        .collect::<Vec<String>>()
+                )
+            ),
+            Expr::Bin(op, lhs, rhs) => {
+                let (level, assoc) = table.get(*op).unwrap_or((0, Assoc::Left));
+                let side = |e: &Expr, tight_side: Assoc| match e {
+                    Expr::Bin(child, ..) => {
+                        let (child_level, _) = table.get(*child).unwrap_or((0, Assoc::Left));
+                        if child_level < level || (child_level == level && assoc != tight_side) {
+                            format!("({})", e.render(table))
+                        } else {
+                            e.render(table)
+                        }
+                    }
+                    _ => e.render(table),
+                };
+                format!(
+                    "{} {} {}",
+                    side(lhs, Assoc::Left),
+                    op,
+                    side(rhs, Assoc::Right)
+                )
+            }
+        }
+    }
+
+    // Works out the leftmost operation whose operands are all plain numbers,
+    // or returns None for a number
+    pub fn reduce_step(&self) -> Result<Option<Expr>, EvalError> {
+        match self {
+            Expr::Num(_) => Ok(None),
+            Expr::Var(name) => Err(EvalError::UnknownVariable(name.clone())),
+            Expr::Neg(e) => match &**e {
+                Expr::Num(n) => Ok(Some(Expr::Num(negate(*n)?))),
+                // A minus in front of a number is just a negative number, not a step of its own
+                _ => match e.reduce_step()? {
+                    Some(Expr::Num(n)) => Ok(Some(Expr::Num(negate(n)?))),
+                    r => Ok(r.map(|r| Expr::Neg(Box::new(r)))),
+                },
+            },
+            Expr::Call(func, args) => match args.iter().position(|a| !matches!(a, Expr::Num(_))) {
+                None => {
+                    let values = args
+                        .iter()
+                        .map(|a| match a {
+                            Expr::Num(n) => *n,
+                            _ => unreachable!(),
+                        })
+                        .collect::<Vec<i128>>();
+                    Ok(Some(Expr::Num(func.apply(&values)?)))
+                }
+                Some(i) => Ok(args[i].reduce_step()?.map(|r| {
+                    let mut args = args.clone();
+                    args[i] = r;
+                    Expr::Call(*func, args)
+                })),
+            },
+            Expr::Bin(op, lhs, rhs) => match (&**lhs, &**rhs) {
+                (Expr::Num(a), Expr::Num(b)) => Ok(Some(Expr::Num(op.apply(*a, *b)?))),
+                (Expr::Num(_), _) => Ok(rhs
+                    .reduce_step()?
+                    .map(|r| Expr::Bin(*op, lhs.clone(), Box::new(r)))),
+                _ => Ok(lhs
+                    .reduce_step()?
+                    .map(|l| Expr::Bin(*op, Box::new(l), rhs.clone()))),
+            },
+        }
+    }
+
+    // The expression after each reduction, from the start down to the final number
+    pub fn trace(&self, table: &OperatorTable) -> Result<Vec<String>, EvalError> {
+        let mut res = vec![self.render(table)];
+        let mut cur = self.clone();
+        while let Some(next) = cur.reduce_step()? {
+            res.push(next.render(table));
+            cur = next;
+        }
+        Ok(res)
+    }
+}
+
+pub fn evaluate(table: &OperatorTable, line: &str) -> Result<i128, Box<dyn Error>> {
+    Ok(parse_line(line)?.to_expr(table)?.eval()?)
+}
+
+pub fn solver(table: &OperatorTable, input: &[Chain]) -> Result<i128, EvalError> {
+    input.iter().try_fold(0i128, |acc, chain| {
+        BinOp::Add.apply(acc, chain.to_expr(table)?.eval()?)
+    })
+}
+
+#[aoc_generator(day18)]
+pub fn input_generator(input: &str) -> Result<Vec<Chain>, SyntaxError> {
     input
         .lines()
-        .map(|s| CalcParser::parse(Rule::calculation, s).unwrap())
-        .collect::<Vec<Pairs<Rule>>>()
+        .enumerate()
+        .map(|(n, l)| parse_line(l).map_err(|e| SyntaxError { line: n + 1, ..e }))
+        .collect()
 }
 
 #[aoc(day18, part1)]
-pub fn part1(input: &str) -> f64 {
-    let input = input
-        .lines()
-        .map(|s| CalcParser::parse(Rule::calculation, s).unwrap())
-        .collect::<Vec<Pairs<Rule>>>();
-
-    input
-        .iter()
-        .map(|p| eval(&*PREC_CLIMBER_P1, p.clone()))
-        .sum()
+pub fn part1(input: &[Chain]) -> Result<i128, EvalError> {
+    solver(&OperatorTable::part1(), input)
 }
 
 #[aoc(day18, part2)]
-pub fn part2(input: &str) -> f64 {
-    let input = input
-        .lines()
-        .map(|s| CalcParser::parse(Rule::calculation, s).unwrap())
-        .collect::<Vec<Pairs<Rule>>>();
-
-    input
-        .iter()
-        .map(|p| eval(&*PREC_CLIMBER_P2, p.clone()))
-        .sum()
+pub fn part2(input: &[Chain]) -> Result<i128, EvalError> {
+    solver(&OperatorTable::part2(), input)
 }
 
 #[cfg(test)]
@@ -94,16 +700,229 @@ mod tests {
     use super::*;
 
     const INPUT: &str = "1 + 2 * 3 + 4 * 5 + 6";
+    const INPUT2: &str = "((2 + 4 * 9) * (6 + 9 * 8 + 6) + 6) + 2 + 4 * 2";
+
     #[test]
-    pub fn test_generator() {}
+    pub fn test_generator() {
+        let chains = input_generator("1 + (2 * 3)\n4").unwrap();
+        assert_eq!(
+            chains[0],
+            Chain {
+                terms: vec![
+                    Term::Num(1),
+                    Term::Group(Chain {
+                        terms: vec![Term::Num(2), Term::Num(3)],
+                        ops: vec![BinOp::Mul]
+                    })
+                ],
+                ops: vec![BinOp::Add]
+            }
+        );
+        assert_eq!(chains[0].to_string(), "1 + (2 * 3)");
+        assert_eq!(input_generator(INPUT2).unwrap()[0].to_string(), INPUT2);
+    }
 
     #[test]
     pub fn test_part1() {
-        assert_eq!(part1(&INPUT), 71.0);
+        assert_eq!(part1(&input_generator(INPUT).unwrap()).unwrap(), 71);
+        assert_eq!(part1(&input_generator(INPUT2).unwrap()).unwrap(), 13632);
     }
 
     #[test]
     pub fn test_part2() {
-        assert_eq!(part2(&INPUT), 231.0);
+        assert_eq!(part2(&input_generator(INPUT).unwrap()).unwrap(), 231);
+        assert_eq!(part2(&input_generator(INPUT2).unwrap()).unwrap(), 23340);
+    }
+
+    #[test]
+    pub fn test_syntax_errors() {
+        let err = |s: &str| input_generator(s).unwrap_err().to_string();
+        assert_eq!(err("1 + 2\n3 + * 4"), "line 2, column 5: expected operand");
+        assert_eq!(err("(1 + 2"), "line 1, column 7: expected operator");
+        assert_eq!(
+            err("1 + 2)"),
+            "line 1, column 6: expected end of line or operator"
+        );
+        assert_eq!(
+            err("1 + 99999999999999999999999999999999999999999"),
+            "line 1, column 5: number out of range: 99999999999999999999999999999999999999999"
+        );
+    }
+
+    #[test]
+    pub fn test_printing() {
+        let chain = parse_line(INPUT).unwrap();
+        let p1 = chain.to_expr(&OperatorTable::part1()).unwrap();
+        let p2 = chain.to_expr(&OperatorTable::part2()).unwrap();
+        assert_eq!(p1.explicit(), "((((1 + 2) * 3) + 4) * 5) + 6");
+        assert_eq!(p2.explicit(), "((1 + 2) * (3 + 4)) * (5 + 6)");
+        // Parentheses only where the standard rules would group differently
+        assert_eq!(
+            p1.render(&OperatorTable::standard()),
+            "((1 + 2) * 3 + 4) * 5 + 6"
+        );
+        assert_eq!(
+            p2.render(&OperatorTable::standard()),
+            "(1 + 2) * (3 + 4) * (5 + 6)"
+        );
+        assert_eq!(p2.render(&OperatorTable::part2()), INPUT);
+
+        let right = parse_line("2 ^ 3 ^ 2 - (1 - 1)")
+            .unwrap()
+            .to_expr(&OperatorTable::standard())
+            .unwrap();
+        assert_eq!(
+            right.render(&OperatorTable::standard()),
+            "2 ^ 3 ^ 2 - (1 - 1)"
+        );
+    }
+
+    #[test]
+    pub fn test_trace() {
+        let chain = parse_line(INPUT).unwrap();
+        let table = OperatorTable::part1();
+        assert_eq!(
+            chain.to_expr(&table).unwrap().trace(&table).unwrap(),
+            vec![
+                "1 + 2 * 3 + 4 * 5 + 6",
+                "3 * 3 + 4 * 5 + 6",
+                "9 + 4 * 5 + 6",
+                "13 * 5 + 6",
+                "65 + 6",
+                "71"
+            ]
+        );
+        let table = OperatorTable::part2();
+        assert_eq!(
+            chain.to_expr(&table).unwrap().trace(&table).unwrap()[..3],
+            [
+                "1 + 2 * 3 + 4 * 5 + 6",
+                "3 * 3 + 4 * 5 + 6",
+                "3 * 7 * 5 + 6"
+            ]
+        );
+        assert!(parse_line("1 + 2 / 0")
+            .unwrap()
+            .to_expr(&table)
+            .unwrap()
+            .trace(&table)
+            .is_err());
+    }
+
+    #[test]
+    pub fn test_exact() {
+        // An f64 would round this away
+        assert_eq!(
+            evaluate(&OperatorTable::part1(), "9007199254740993 + 1").unwrap(),
+            9007199254740994
+        );
+        assert_eq!(evaluate(&OperatorTable::part1(), "7 / 2 - 5").unwrap(), -2);
+        assert_eq!(evaluate(&OperatorTable::part1(), "2 ^ 3 ^ 2").unwrap(), 512);
+
+        let err = |s: &str| {
+            evaluate(&OperatorTable::part1(), s)
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(err("1 / (2 - 2)"), "division by zero");
+        assert_eq!(err("2 ^ 127"), "2 ^ 127 overflows");
+        assert_eq!(err("2 ^ -1"), "negative exponent -1");
+        assert!(evaluate(&OperatorTable::part1(), "1 + 1.5").is_err());
+    }
+
+    #[test]
+    pub fn test_operator_table() {
+        let standard = OperatorTable::standard();
+        assert_eq!(evaluate(&standard, INPUT).unwrap(), 33);
+        assert_eq!(evaluate(&standard, "10 - 4 - 3").unwrap(), 3);
+
+        // Right associative subtraction, built at runtime
+        let odd = OperatorTable::standard().with(BinOp::Sub, 1, Assoc::Right);
+        assert_eq!(evaluate(&odd, "10 - 4 - 3").unwrap(), 9);
+
+        let partial = OperatorTable::new().with(BinOp::Add, 1, Assoc::Left);
+        assert_eq!(evaluate(&partial, "1 + 2").unwrap(), 3);
+        assert_eq!(
+            evaluate(&partial, "1 + 2 * 3").unwrap_err().to_string(),
+            "no precedence given for *"
+        );
+
+        // The top level still works for left associative operators
+        let top = OperatorTable::new()
+            .with(BinOp::Add, 255, Assoc::Left)
+            .with(BinOp::Mul, 0, Assoc::Left);
+        assert_eq!(evaluate(&top, "1 + 2").unwrap(), 3);
+        assert_eq!(evaluate(&top, "2 * 3 + 4 + 5").unwrap(), 24);
+    }
+
+    #[test]
+    pub fn test_variables_and_functions() {
+        let table = OperatorTable::standard();
+        let eval = |s: &str, env: &Env| {
+            parse_line(s)
+                .map_err(|e| e.to_string())?
+                .to_expr(&table)
+                .and_then(|e| e.substitute(env).eval())
+                .map_err(|e| e.to_string())
+        };
+        let mut env = Env::new();
+        env.insert(String::from("x"), 7);
+        env.insert(String::from("y_2"), -3);
+
+        assert_eq!(eval("x * y_2 + 1", &env), Ok(-20));
+        assert_eq!(eval("-x", &env), Ok(-7));
+        assert_eq!(eval("-2 ^ 2", &env), Ok(4));
+        assert_eq!(eval("-x ^ 2", &env), Ok(49));
+        assert_eq!(eval("0 - 2 ^ 2", &env), Ok(-4));
+        assert_eq!(eval("- (x + 1) * 2", &env), Ok(-16));
+        assert_eq!(eval("1 - -x", &env), Ok(8));
+        assert_eq!(eval("min(x, y_2, 4) + max(1)", &env), Ok(-2));
+        assert_eq!(eval("abs(y_2) + mod(-7, 3)", &env), Ok(5));
+        assert_eq!(eval("mod(max(x, 10), 4 - 1)", &env), Ok(1));
+
+        assert_eq!(eval("z + 1", &env), Err(String::from("unknown variable z")));
+        assert_eq!(
+            eval("sqrt(4)", &env),
+            Err(String::from("unknown function sqrt"))
+        );
+        assert_eq!(
+            eval("abs(1, 2)", &env),
+            Err(String::from("abs takes 1 argument(s), got 2"))
+        );
+        assert_eq!(
+            eval("min()", &env),
+            Err(String::from("min takes at least 1 argument(s), got 0"))
+        );
+        assert_eq!(
+            eval("mod(1, 0)", &env),
+            Err(String::from("division by zero"))
+        );
+
+        let e = parse_line("-(1 + x) * abs(-2)")
+            .unwrap()
+            .to_expr(&table)
+            .unwrap();
+        assert_eq!(e.render(&table), "-(1 + x) * abs(-2)");
+        assert_eq!(e.explicit(), "-(1 + x) * abs(-2)");
+        assert_eq!(
+            e.substitute(&env).trace(&table).unwrap(),
+            vec!["-(1 + 7) * abs(-2)", "-8 * abs(-2)", "-8 * 2", "-16"]
+        );
+    }
+
+    #[test]
+    pub fn test_statements() {
+        assert_eq!(
+            parse_statement("let x = 1 + 2").unwrap(),
+            Statement::Let(String::from("x"), parse_line("1 + 2").unwrap())
+        );
+        assert_eq!(
+            parse_statement("letter + 1").unwrap(),
+            Statement::Eval(parse_line("letter + 1").unwrap())
+        );
+        assert_eq!(
+            parse_statement("let 1 = 2").unwrap_err().to_string(),
+            "line 1, column 5: expected name"
+        );
     }
 }
@@ -0,0 +1,240 @@
+use crate::day18::{parse_statement, Chain, Env, OperatorTable, Statement};
+use std::error::Error;
+use std::io::{BufRead, Write};
+
+// An interactive calculator over the day18 expression language
+// Like the day8 debugger it only talks to a BufRead and a Write, so stdin, a pipe or a test
+// string all drive it the same way
+
+const HELP: &str = "enter an expression to evaluate it, or bind a name with: let x = 1 + 2
+functions: min(a, ...), max(a, ...), abs(a), mod(a, b)
+a leading minus binds tighter than any operator, so -2 ^ 2 is 4
+commands:
+  :part1          every operator on one level, left to right (the default)
+  :part2          + and - before * and /
+  :standard       * and / before + and -
+  :trace <expr>   show each reduction step
+  :explicit <expr> show how the current rules group the expression
+  :vars           list bound names
+  :help           show this text
+  :quit           leave";
+
+pub struct Repl {
+    table: OperatorTable,
+    mode: &'static str,
+    env: Env,
+    quit: bool,
+}
+
+impl Default for Repl {
+    fn default() -> Repl {
+        Repl::new()
+    }
+}
+
+impl Repl {
+    pub fn new() -> Repl {
+        Repl {
+            table: OperatorTable::part1(),
+            mode: "part1",
+            env: Env::new(),
+            quit: false,
+        }
+    }
+
+    // Name of the precedence table in use
+    pub fn mode(&self) -> &str {
+        self.mode
+    }
+
+    // Whether :quit has been entered
+    pub fn quit(&self) -> bool {
+        self.quit
+    }
+
+    pub fn get(&self, name: &str) -> Option<i128> {
+        self.env.get(name).copied()
+    }
+
+    fn value(&self, chain: &Chain) -> Result<i128, Box<dyn Error>> {
+        Ok(chain.to_expr(&self.table)?.substitute(&self.env).eval()?)
+    }
+
+    fn parse_expr(line: &str) -> Result<Chain, Box<dyn Error>> {
+        match parse_statement(line)? {
+            Statement::Eval(chain) => Ok(chain),
+            Statement::Let(..) => Err("expected an expression, not a binding".into()),
+        }
+    }
+
+    // Handles one line of input and returns what to print, if anything
+    pub fn line(&mut self, line: &str) -> Result<Option<String>, Box<dyn Error>> {
+        let line = line.trim();
+        if line.is_empty() {
+            return Ok(None);
+        }
+        if let Some(cmd) = line.strip_prefix(':') {
+            let (cmd, rest) = match cmd.find(' ') {
+                Some(i) => (&cmd[..i], cmd[i + 1..].trim()),
+                None => (cmd, ""),
+            };
+            // Commands with nothing to say, like :quit or :vars with no names, print nothing
+            return self
+                .command(cmd, rest)
+                .map(|res| if res.is_empty() { None } else { Some(res) });
+        }
+        match parse_statement(line)? {
+            Statement::Let(name, chain) => {
+                let v = self.value(&chain)?;
+                self.env.insert(name.clone(), v);
+                Ok(Some(format!("{} = {}", name, v)))
+            }
+            Statement::Eval(chain) => Ok(Some(self.value(&chain)?.to_string())),
+        }
+    }
+
+    fn command(&mut self, cmd: &str, rest: &str) -> Result<String, Box<dyn Error>> {
+        let (table, mode) = match cmd {
+            "part1" => (OperatorTable::part1(), "part1"),
+            "part2" => (OperatorTable::part2(), "part2"),
+            "standard" => (OperatorTable::standard(), "standard"),
+            "trace" => {
+                let expr = Repl::parse_expr(rest)?
+                    .to_expr(&self.table)?
+                    .substitute(&self.env);
+                return Ok(expr.trace(&self.table)?.join("\n"));
+            }
+            "explicit" => {
+                let expr = Repl::parse_expr(rest)?.to_expr(&self.table)?;
+                return Ok(expr.explicit());
+            }
+            "vars" => {
+                let mut vars = self
+                    .env
+                    .iter()
+                    .map(|(k, v)| format!("{} = {}", k, v))
+                    .collect::<Vec<String>>();
+                vars.sort();
+                return Ok(vars.join("\n"));
+            }
+            "help" => return Ok(String::from(HELP)),
+            "q" | "quit" => {
+                self.quit = true;
+                return Ok(String::new());
+            }
+            _ => return Err(format!("unknown command :{}, try :help", cmd).into()),
+        };
+        self.table = table;
+        self.mode = mode;
+        Ok(format!("using {} precedence", mode))
+    }
+
+    // Read lines until :quit or end of input
+    pub fn run<R: BufRead, W: Write>(
+        &mut self,
+        input: R,
+        mut output: W,
+    ) -> Result<(), Box<dyn Error>> {
+        write!(output, "{}> ", self.mode)?;
+        output.flush()?;
+        for line in input.lines() {
+            match self.line(&line?) {
+                Ok(Some(res)) => writeln!(output, "{}", res)?,
+                Ok(None) => {}
+                Err(e) => writeln!(output, "error: {}", e)?,
+            }
+            if self.quit {
+                break;
+            }
+            write!(output, "{}> ", self.mode)?;
+            output.flush()?;
+        }
+        writeln!(output)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(script: &str) -> String {
+        let mut out = vec![];
+        Repl::new().run(script.as_bytes(), &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    pub fn test_bindings() {
+        let mut repl = Repl::new();
+        assert_eq!(
+            repl.line("let x = 2 + 3 * 4").unwrap(),
+            Some(String::from("x = 20"))
+        );
+        assert_eq!(repl.line("x - -1").unwrap(), Some(String::from("21")));
+        assert_eq!(
+            repl.line("let y = max(x, abs(-50)) / mod(7, 4)").unwrap(),
+            Some(String::from("y = 16"))
+        );
+        assert_eq!(repl.get("y"), Some(16));
+        assert_eq!(repl.line("  ").unwrap(), None);
+        assert_eq!(
+            repl.line("z").unwrap_err().to_string(),
+            "unknown variable z"
+        );
+        // A failed binding leaves the old value alone
+        assert!(repl.line("let x = 1 / 0").is_err());
+        assert_eq!(repl.get("x"), Some(20));
+        assert_eq!(
+            repl.line(":vars").unwrap(),
+            Some(String::from("x = 20\ny = 16"))
+        );
+        assert_eq!(Repl::new().line(":vars").unwrap(), None);
+    }
+
+    #[test]
+    pub fn test_modes() {
+        let mut repl = Repl::new();
+        assert_eq!(repl.mode(), "part1");
+        assert_eq!(repl.line("1 + 2 * 3").unwrap(), Some(String::from("9")));
+        assert_eq!(
+            repl.line(":part2").unwrap(),
+            Some(String::from("using part2 precedence"))
+        );
+        assert_eq!(
+            repl.line("2 * 3 + (4 * 5)").unwrap(),
+            Some(String::from("46"))
+        );
+        assert_eq!(
+            repl.line(":explicit 1 + 2 * 3 + 4").unwrap(),
+            Some(String::from("(1 + 2) * (3 + 4)"))
+        );
+        repl.line(":standard").unwrap();
+        assert_eq!(repl.line("1 + 2 * 3").unwrap(), Some(String::from("7")));
+        assert_eq!(
+            repl.line(":trace 1 + 2 * 3").unwrap(),
+            Some(String::from("1 + 2 * 3\n1 + 6\n7"))
+        );
+        assert!(repl.line(":trace let a = 1").is_err());
+        assert_eq!(
+            repl.line(":fly").unwrap_err().to_string(),
+            "unknown command :fly, try :help"
+        );
+
+        assert!(!repl.quit());
+        assert_eq!(repl.line(":quit").unwrap(), None);
+        assert!(repl.quit());
+        let mut repl = Repl::new();
+        assert_eq!(repl.line(":q").unwrap(), None);
+        assert!(repl.quit());
+    }
+
+    #[test]
+    pub fn test_session() {
+        assert_eq!(
+            session("let a = 5\na * 2 + 1\n:part2\na * 2 + 1\n1 +\n:quit\n7"),
+            "part1> a = 5\npart1> 11\npart1> using part2 precedence\npart2> 15\npart2> \
+             error: line 1, column 4: expected operand\npart2> \n"
+        );
+    }
+}
@@ -1,106 +1,202 @@
 use aoc_runner_derive::{aoc, aoc_generator};
-use std::collections::HashSet;
+use std::fmt;
 
-// TEMPLATE
+// Each person answers "yes" to some of the 26 questions a-z
+// A u32 bitmask is plenty to hold that, with bit 0 standing for 'a' and bit 25 for 'z'
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct AnswerSet(u32);
 
-#[aoc_generator(day6)]
-pub fn input_generator(input: &str) -> Vec<Vec<HashSet<char>>> {
-    input
-        .split("\n\n")
-        .map(|s| {
-            s.split('\n')
-                .map(|g| {
-                    g.chars().fold(HashSet::new(), |mut a, c| {
-                        a.insert(c);
-                        a
-                    })
-                })
-                .collect::<Vec<HashSet<char>>>()
+impl AnswerSet {
+    pub const QUESTIONS: usize = 26;
+
+    pub fn new() -> AnswerSet {
+        AnswerSet(0)
+    }
+
+    pub fn all() -> AnswerSet {
+        AnswerSet((1 << AnswerSet::QUESTIONS) - 1)
+    }
+
+    // Anything outside of a-z is not a question, so it is quietly ignored
+    pub fn from_answers(s: &str) -> AnswerSet {
+        s.chars().fold(AnswerSet::new(), |mut a, c| {
+            a.insert(c);
+            a
         })
-        .collect::<Vec<Vec<HashSet<char>>>>()
+    }
+
+    fn bit(c: char) -> Option<u32> {
+        if c.is_ascii_lowercase() {
+            Some(1 << (c as u32 - 'a' as u32))
+        } else {
+            None
+        }
+    }
+
+    pub fn insert(&mut self, c: char) -> bool {
+        if let Some(b) = AnswerSet::bit(c) {
+            let fresh = self.0 & b == 0;
+            self.0 |= b;
+            fresh
+        } else {
+            false
+        }
+    }
+
+    pub fn contains(&self, c: char) -> bool {
+        AnswerSet::bit(c).is_some_and(|b| self.0 & b != 0)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.count_ones() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn union(&self, other: &AnswerSet) -> AnswerSet {
+        AnswerSet(self.0 | other.0)
+    }
+
+    pub fn intersection(&self, other: &AnswerSet) -> AnswerSet {
+        AnswerSet(self.0 & other.0)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = char> + '_ {
+        (0..AnswerSet::QUESTIONS as u32)
+            .filter(move |i| self.0 & (1 << i) != 0)
+            .map(|i| (b'a' + i as u8) as char)
+    }
 }
 
-/* pub fn input_generator(input: &str) -> Vec<String> {
-    input
-        .split("\n\n")
-        .map(|s| String::from(s))
-        .collect::<Vec<String>>()
+impl fmt::Display for AnswerSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.iter().collect::<String>())
+    }
 }
- */
 
-#[aoc(day6, part1)]
-pub fn part1(input: &Vec<Vec<HashSet<char>>>) -> usize {
-    input
-        .iter()
-        .map(|el| {
-            el.iter()
-                .fold(HashSet::new(), |a, hs| a.union(hs).copied().collect())
-                .len()
-        })
-        .sum()
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Group {
+    people: Vec<AnswerSet>,
 }
 
-/* pub fn part1(input: &Vec<String>) -> usize {
-    input
-        .iter()
-        .map(|s| s.split('\n').collect::<String>())
-        .map(|l| {
-            l.chars()
-                .fold(HashSet::new(), |mut a, c| {
-                    a.insert(c);
-                    a
-                })
-                .len()
-        })
-        .sum()
+impl Group {
+    pub fn new(people: Vec<AnswerSet>) -> Group {
+        Group { people }
+    }
+
+    pub fn people(&self) -> &[AnswerSet] {
+        &self.people
+    }
+
+    pub fn len(&self) -> usize {
+        self.people.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.people.is_empty()
+    }
+
+    // Questions anyone in the group answered
+    pub fn union(&self) -> AnswerSet {
+        self.people.iter().fold(AnswerSet::new(), |a, p| a.union(p))
+    }
+
+    // Questions everyone in the group answered; nobody in the group means nobody answered anything
+    pub fn intersection(&self) -> AnswerSet {
+        if self.people.is_empty() {
+            return AnswerSet::new();
+        }
+        self.people
+            .iter()
+            .fold(AnswerSet::all(), |a, p| a.intersection(p))
+    }
+
+    // How many people in the group answered each question, indexed 0 for 'a' to 25 for 'z'
+    pub fn histogram(&self) -> [usize; AnswerSet::QUESTIONS] {
+        let mut res = [0; AnswerSet::QUESTIONS];
+        self.people
+            .iter()
+            .for_each(|p| p.iter().for_each(|c| res[(c as u8 - b'a') as usize] += 1));
+        res
+    }
+
+    // Questions answered by at least k people of the group
+    // quorum(1) is the union and quorum(len()) is the intersection
+    pub fn quorum(&self, k: usize) -> AnswerSet {
+        self.histogram()
+            .iter()
+            .enumerate()
+            .filter(|(_, &n)| n >= k)
+            .fold(AnswerSet::new(), |mut a, (i, _)| {
+                a.insert((b'a' + i as u8) as char);
+                a
+            })
+    }
+
+    pub fn quorum_count(&self, k: usize) -> usize {
+        self.quorum(k).len()
+    }
 }
- */
 
-#[aoc(day6, part2)]
-pub fn part2(input: &Vec<Vec<HashSet<char>>>) -> usize {
-    input
-        .iter()
-        .map(|vhs| {
-            let mut iter = vhs.iter();
-            if let Some(hs) = iter
-                .next()
-                .cloned()
-                .map(|i| iter.fold(i, |a, hs| a.intersection(hs).copied().collect()))
-            {
-                hs.len()
-            } else {
-                0
+// Per-question report across all groups:
+// how many people said "yes", in how many groups anyone did, and in how many groups everyone did
+pub fn histogram_report(groups: &[Group]) -> String {
+    let mut people = [0; AnswerSet::QUESTIONS];
+    let mut anyone = [0; AnswerSet::QUESTIONS];
+    let mut everyone = [0; AnswerSet::QUESTIONS];
+
+    groups.iter().for_each(|g| {
+        let hist = g.histogram();
+        (0..AnswerSet::QUESTIONS).for_each(|i| {
+            people[i] += hist[i];
+            if hist[i] > 0 {
+                anyone[i] += 1;
             }
+            if hist[i] > 0 && hist[i] == g.len() {
+                everyone[i] += 1;
+            }
+        });
+    });
+
+    (0..AnswerSet::QUESTIONS)
+        .map(|i| {
+            format!(
+                "{}: {:>4} people, {:>4} groups (anyone), {:>4} groups (everyone)\n",
+                (b'a' + i as u8) as char,
+                people[i],
+                anyone[i],
+                everyone[i]
+            )
         })
-        .sum()
+        .collect()
 }
 
-/* pub fn part2(input: &Vec<String>) -> usize {
+#[aoc_generator(day6)]
+pub fn input_generator(input: &str) -> Vec<Group> {
     input
-        .iter()
+        .split("\n\n")
         .map(|s| {
-            // in each group, replace each person with a HashSet of their "yes" answers
-            let mut iter = s.split('\n').map(|g| {
-                g.chars().fold(HashSet::new(), |mut a, c| {
-                    a.insert(c);
-                    a
-                })
-            });
-            // now iter has the iterator over HashSets representing each person in the group
-            // then the below trickery is to calculate the intersection of several HashSets
-            // and identify how many questions were answered the same by everyone in the group
-            if let Some(hs) = iter
-                .next()
-                .map(|i| iter.fold(i, |hs1, hs2| hs1.intersection(&hs2).copied().collect()))
-            {
-                hs.len()
-            } else {
-                0
-            }
+            Group::new(
+                s.lines()
+                    .filter(|l| !l.is_empty())
+                    .map(AnswerSet::from_answers)
+                    .collect::<Vec<AnswerSet>>(),
+            )
         })
-        .sum()
+        .collect::<Vec<Group>>()
+}
+
+#[aoc(day6, part1)]
+pub fn part1(input: &[Group]) -> usize {
+    input.iter().map(|g| g.union().len()).sum()
+}
+
+#[aoc(day6, part2)]
+pub fn part2(input: &[Group]) -> usize {
+    input.iter().map(|g| g.intersection().len()).sum()
 }
- */
 
 #[cfg(test)]
 mod tests {
@@ -124,17 +220,51 @@ b";
 
     #[test]
     pub fn test_generator() {
-        /*         assert_eq!(
-                   input_generator(INPUT),
-                   vec![
-                       String::from("abc"),
-                       String::from("a\nb\nc"),
-                       String::from("ab\nac"),
-                       String::from("a\na\na\na"),
-                       String::from("b")
-                   ]
-               );
-        */
+        let groups = input_generator(INPUT);
+        assert_eq!(groups.len(), 5);
+        assert_eq!(
+            groups[2],
+            Group::new(vec![
+                AnswerSet::from_answers("ab"),
+                AnswerSet::from_answers("ac")
+            ])
+        );
+        assert_eq!(groups[1].union().to_string(), "abc");
+    }
+
+    #[test]
+    pub fn test_answer_set() {
+        let a = AnswerSet::from_answers("abcz");
+        let b = AnswerSet::from_answers("bz?");
+        assert_eq!(a.len(), 4);
+        assert!(a.contains('z'));
+        assert!(!b.contains('?'));
+        assert_eq!(a.intersection(&b), AnswerSet::from_answers("zb"));
+        assert_eq!(a.union(&b), a);
+    }
+
+    #[test]
+    pub fn test_quorum() {
+        let g = Group::new(vec![
+            AnswerSet::from_answers("abc"),
+            AnswerSet::from_answers("ab"),
+            AnswerSet::from_answers("ad"),
+        ]);
+        assert_eq!(g.quorum(1), g.union());
+        assert_eq!(g.quorum(2).to_string(), "ab");
+        assert_eq!(g.quorum(3), g.intersection());
+        assert_eq!(g.quorum_count(4), 0);
+        assert_eq!(g.histogram()[0..4], [3, 2, 1, 1]);
+    }
+
+    #[test]
+    pub fn test_histogram_report() {
+        let report = histogram_report(&input_generator(INPUT));
+        assert_eq!(report.lines().count(), 26);
+        assert_eq!(
+            report.lines().next().unwrap(),
+            "a:    8 people,    4 groups (anyone),    3 groups (everyone)"
+        );
     }
 
     #[test]
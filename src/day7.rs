@@ -1,20 +1,56 @@
 use aoc_runner_derive::{aoc, aoc_generator};
 use lazy_static::lazy_static;
-//use petgraph::dot::Dot;
+use petgraph::algo::tarjan_scc;
 use petgraph::graph::{Graph, NodeIndex};
+use petgraph::visit::EdgeRef;
 use petgraph::Direction;
 use regex::Regex;
-use std::collections::HashMap;
-use std::collections::HashSet;
+use simple_error::*;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::error::Error;
+use std::fmt::Write;
 
 // The regex to extract from input
 lazy_static! {
     static ref LINEPARSER: Regex = Regex::new(r"^([a-z ]+) bags contain ([0-9a-z ,]+)\.$").unwrap();
-    static ref BAGPARSER: Regex = Regex::new(r"(\d) ([a-z ]+) bag[s]?").unwrap();
+    static ref BAGPARSER: Regex = Regex::new(r"(\d+) ([a-z ]+) bag[s]?").unwrap();
+}
+
+// The rules as a graph: an edge a -> b with weight n means "b contains n of a"
+// So outgoing edges lead to containers and incoming edges lead to contents
+// Colours only ever mentioned inside other bags never get a rule of their own, so they are not in defined
+pub struct BagRules {
+    graph: Graph<String, usize>,
+    index: HashMap<String, NodeIndex>,
+    defined: HashSet<NodeIndex>,
+}
+
+// What validate() finds wrong with a rule set; every list is sorted
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct RulesReport {
+    // Each cycle is the set of colours that end up inside each other
+    pub cycles: Vec<Vec<String>>,
+    // Colours that directly contain themselves
+    pub self_containing: Vec<String>,
+    // Colours referenced by some rule but without a rule of their own
+    pub undefined: Vec<String>,
+}
+
+impl RulesReport {
+    pub fn is_ok(&self) -> bool {
+        self.cycles.is_empty() && self.self_containing.is_empty() && self.undefined.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PathKind {
+    Shortest,
+    Longest,
 }
 
 #[aoc_generator(day7)]
-pub fn input_generator(input: &str) -> (Graph<String, usize>, HashMap<String, NodeIndex>) {
+pub fn input_generator(input: &str) -> BagRules {
     // The input is most representative of a DAG.
     // So it makes sense to parse out input line-by-line and build a representative DAG out of it
     // I have a sneaky suspicion that the number of bags will be important, so it is worth assigning
@@ -22,6 +58,7 @@ pub fn input_generator(input: &str) -> (Graph<String, usize>, HashMap<String, No
     // It may be useful to output a HashMap, mapping each node to NodeIndex as well
     let mut res1 = Graph::<String, usize>::new();
     let mut res2 = HashMap::<String, NodeIndex>::new();
+    let mut defined = HashSet::<NodeIndex>::new();
     // Parsing input line-by-line, parsing each line into >= 2 nodes and adding edges with the number as the weight
     input.lines().for_each(|l| {
         if let Some(caps) = LINEPARSER.captures(l) {
@@ -39,6 +76,7 @@ pub fn input_generator(input: &str) -> (Graph<String, usize>, HashMap<String, No
                 res2.insert(b.clone(), new_node_id);
                 b_node_id = Some(new_node_id);
             }
+            defined.insert(b_node_id.unwrap());
 
             caps.get(2).unwrap().as_str().split(", ").for_each(|s| {
                 if let Some(bags) = BAGPARSER.captures(s) {
@@ -55,42 +93,337 @@ pub fn input_generator(input: &str) -> (Graph<String, usize>, HashMap<String, No
             });
         }
     });
-    // Useful to visualise the resulting DAGraph
-    //eprintln!("{:?}", Dot::with_config(&res1, &[]));
-    (res1, res2)
+    // Useful to visualise the resulting DAGraph: see BagRules::to_dot
+    BagRules {
+        graph: res1,
+        index: res2,
+        defined,
+    }
 }
 
-fn count_all_containing(g: &Graph<String, usize>, idx: NodeIndex) -> HashSet<NodeIndex> {
-    let mut res: HashSet<NodeIndex> = HashSet::new();
-    g.neighbors(idx).for_each(|n| {
-        res.insert(n);
-        res = res.union(&count_all_containing(g, n)).copied().collect();
-    });
-    res
+impl BagRules {
+    pub fn colours(&self) -> impl Iterator<Item = &str> {
+        self.index.keys().map(|k| k.as_str())
+    }
+
+    fn node(&self, colour: &str) -> Result<NodeIndex, Box<dyn Error>> {
+        match self.index.get(colour) {
+            Some(idx) => Ok(*idx),
+            None => bail!("unknown bag colour: {}", colour),
+        }
+    }
+
+    fn name(&self, idx: NodeIndex) -> &str {
+        self.graph[idx].as_str()
+    }
+
+    // Every (inner bag, count) directly inside idx
+    fn contents(&self, idx: NodeIndex) -> impl Iterator<Item = (NodeIndex, usize)> + '_ {
+        self.graph
+            .edges_directed(idx, Direction::Incoming)
+            .map(|e| (e.source(), *e.weight()))
+    }
+
+    // All the colours that eventually contain the given one
+    // A plain graph walk remembering what it has seen, so every bag is visited once
+    pub fn containers_of(&self, colour: &str) -> Result<HashSet<&str>, Box<dyn Error>> {
+        let start = self.node(colour)?;
+        let mut seen: HashSet<NodeIndex> = HashSet::new();
+        let mut stack = vec![start];
+        while let Some(idx) = stack.pop() {
+            self.graph.neighbors(idx).for_each(|n| {
+                if seen.insert(n) {
+                    stack.push(n);
+                }
+            });
+        }
+        Ok(seen.into_iter().map(|idx| self.name(idx)).collect())
+    }
+
+    // Memoised depth-first walk over contents
+    // f combines the already computed results of the inner bags into the result for the outer bag idx
+    // The on-stack set catches cyclic rules instead of recursing forever
+    fn memoised<T, F>(
+        &self,
+        idx: NodeIndex,
+        memo: &mut HashMap<NodeIndex, T>,
+        onstack: &mut HashSet<NodeIndex>,
+        f: &F,
+    ) -> Result<T, Box<dyn Error>>
+    where
+        T: Clone,
+        F: Fn(NodeIndex, &[(NodeIndex, usize, T)]) -> T,
+    {
+        if let Some(res) = memo.get(&idx) {
+            return Ok(res.clone());
+        }
+        if !onstack.insert(idx) {
+            bail!("bag rules are cyclic at {}", self.name(idx));
+        }
+        let mut inner = Vec::new();
+        for (i, n) in self.contents(idx).collect::<Vec<_>>() {
+            inner.push((i, n, self.memoised(i, memo, onstack, f)?));
+        }
+        onstack.remove(&idx);
+        let res = f(idx, &inner);
+        memo.insert(idx, res.clone());
+        Ok(res)
+    }
+
+    // How many bags in total are inside the given one
+    pub fn contents_count(&self, colour: &str) -> Result<usize, Box<dyn Error>> {
+        // idea: bags_in_idx = sum_for_neighbours(edge*(1 + count_all_contained(g, neighbour))))
+        let start = self.node(colour)?;
+        self.memoised(
+            start,
+            &mut HashMap::new(),
+            &mut HashSet::new(),
+            &|_, inner: &[(NodeIndex, usize, usize)]| {
+                inner.iter().map(|(_, n, c)| n * (1 + c)).sum()
+            },
+        )
+    }
+
+    // What exactly is inside the given bag, and how many of each colour
+    pub fn bill_of_materials(
+        &self,
+        colour: &str,
+    ) -> Result<BTreeMap<String, usize>, Box<dyn Error>> {
+        let start = self.node(colour)?;
+        let bom = self.memoised(
+            start,
+            &mut HashMap::new(),
+            &mut HashSet::new(),
+            &|_, inner: &[(NodeIndex, usize, HashMap<NodeIndex, usize>)]| {
+                let mut res: HashMap<NodeIndex, usize> = HashMap::new();
+                inner.iter().for_each(|(i, n, bom)| {
+                    *res.entry(*i).or_insert(0) += n;
+                    bom.iter()
+                        .for_each(|(j, m)| *res.entry(*j).or_insert(0) += n * m);
+                });
+                res
+            },
+        )?;
+        Ok(bom
+            .into_iter()
+            .map(|(idx, n)| (String::from(self.name(idx)), n))
+            .collect())
+    }
+
+    // The chain of bags from the outer colour down to the inner one, both included
+    // None if the outer bag can never hold the inner one
+    pub fn containment_path(
+        &self,
+        outer: &str,
+        inner: &str,
+        kind: PathKind,
+    ) -> Result<Option<Vec<&str>>, Box<dyn Error>> {
+        let from = self.node(outer)?;
+        let to = self.node(inner)?;
+        let path = match kind {
+            PathKind::Shortest => self.shortest_path(from, to),
+            PathKind::Longest => self.longest_path(from, to)?,
+        };
+        Ok(path.map(|p| p.into_iter().map(|idx| self.name(idx)).collect()))
+    }
+
+    // Breadth-first search over contents
+    fn shortest_path(&self, from: NodeIndex, to: NodeIndex) -> Option<Vec<NodeIndex>> {
+        let mut prev: HashMap<NodeIndex, NodeIndex> = HashMap::new();
+        let mut queue = VecDeque::new();
+        queue.push_back(from);
+        prev.insert(from, from);
+        while let Some(idx) = queue.pop_front() {
+            if idx == to {
+                let mut path = vec![to];
+                let mut cur = to;
+                while cur != from {
+                    cur = prev[&cur];
+                    path.push(cur);
+                }
+                path.reverse();
+                return Some(path);
+            }
+            self.contents(idx).for_each(|(i, _)| {
+                if let Entry::Vacant(e) = prev.entry(i) {
+                    e.insert(idx);
+                    queue.push_back(i);
+                }
+            });
+        }
+        None
+    }
+
+    // Longest path only makes sense on a DAG, which the memoised walk checks for us
+    fn longest_path(
+        &self,
+        from: NodeIndex,
+        to: NodeIndex,
+    ) -> Result<Option<Vec<NodeIndex>>, Box<dyn Error>> {
+        // For every bag, the longest chain from it down to `to`, stored backwards
+        let mut memo: HashMap<NodeIndex, Option<Vec<NodeIndex>>> = HashMap::new();
+        memo.insert(to, Some(vec![to]));
+        let res = self.memoised(from, &mut memo, &mut HashSet::new(), &|idx,
+                                                                         inner: &[(
+            NodeIndex,
+            usize,
+            Option<Vec<NodeIndex>>,
+        )]| {
+            inner
+                .iter()
+                .filter_map(|(_, _, p)| p.as_ref())
+                .max_by_key(|p| p.len())
+                .map(|p| {
+                    let mut p = p.clone();
+                    p.push(idx);
+                    p
+                })
+        })?;
+        Ok(res.map(|mut p| {
+            p.reverse();
+            p
+        }))
+    }
 }
 
-#[aoc(day7, part1)]
-pub fn part1(input: &(Graph<String, usize>, HashMap<String, NodeIndex>)) -> usize {
-    let (g, hm) = input;
-    let bagidx = hm["shiny gold"];
-    let res = count_all_containing(g, bagidx);
-    res.len()
+impl BagRules {
+    pub fn validate(&self) -> RulesReport {
+        let mut cycles = tarjan_scc(&self.graph)
+            .into_iter()
+            .filter(|scc| scc.len() > 1)
+            .map(|scc| {
+                let mut c = scc
+                    .into_iter()
+                    .map(|idx| String::from(self.name(idx)))
+                    .collect::<Vec<String>>();
+                c.sort();
+                c
+            })
+            .collect::<Vec<Vec<String>>>();
+        cycles.sort();
+
+        let mut self_containing = self
+            .graph
+            .node_indices()
+            .filter(|idx| self.graph.contains_edge(*idx, *idx))
+            .map(|idx| String::from(self.name(idx)))
+            .collect::<Vec<String>>();
+        self_containing.sort();
+
+        let mut undefined = self
+            .graph
+            .node_indices()
+            .filter(|idx| !self.defined.contains(idx))
+            .map(|idx| String::from(self.name(idx)))
+            .collect::<Vec<String>>();
+        undefined.sort();
+
+        RulesReport {
+            cycles,
+            self_containing,
+            undefined,
+        }
+    }
+
+    // The chosen bag together with everything that can end up inside it
+    fn reachable(&self, colour: Option<&str>) -> Result<HashSet<NodeIndex>, Box<dyn Error>> {
+        let mut seen = HashSet::new();
+        if let Some(colour) = colour {
+            let start = self.node(colour)?;
+            let mut stack = vec![start];
+            seen.insert(start);
+            while let Some(idx) = stack.pop() {
+                self.contents(idx).for_each(|(i, _)| {
+                    if seen.insert(i) {
+                        stack.push(i);
+                    }
+                });
+            }
+        }
+        Ok(seen)
+    }
+
+    // Graphviz export, edges pointing from the outer bag to the inner one and labelled with the count
+    // If highlight is given, that bag and the subgraph of its contents are drawn in red
+    pub fn to_dot(&self, highlight: Option<&str>) -> Result<String, Box<dyn Error>> {
+        let hl = self.reachable(highlight)?;
+        let mut res = String::from("digraph bags {\n");
+        for idx in self.graph.node_indices() {
+            write!(res, "    {} [label=\"{}\"", idx.index(), self.name(idx))?;
+            if hl.contains(&idx) {
+                res.push_str(", color=red, fontcolor=red");
+            }
+            res.push_str("];\n");
+        }
+        for e in self.graph.edge_references() {
+            write!(
+                res,
+                "    {} -> {} [label=\"{}\"",
+                e.target().index(),
+                e.source().index(),
+                e.weight()
+            )?;
+            if hl.contains(&e.target()) {
+                res.push_str(", color=red, fontcolor=red");
+            }
+            res.push_str("];\n");
+        }
+        res.push_str("}\n");
+        Ok(res)
+    }
+
+    // GraphML export, same edge direction as to_dot, with the count in a "weight" attribute
+    // and a "highlighted" attribute marking the subgraph of the chosen bag
+    pub fn to_graphml(&self, highlight: Option<&str>) -> Result<String, Box<dyn Error>> {
+        let hl = self.reachable(highlight)?;
+        let mut res = String::from(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n\
+             \x20 <key id=\"colour\" for=\"node\" attr.name=\"colour\" attr.type=\"string\"/>\n\
+             \x20 <key id=\"nhl\" for=\"node\" attr.name=\"highlighted\" attr.type=\"boolean\"/>\n\
+             \x20 <key id=\"weight\" for=\"edge\" attr.name=\"weight\" attr.type=\"int\"/>\n\
+             \x20 <key id=\"ehl\" for=\"edge\" attr.name=\"highlighted\" attr.type=\"boolean\"/>\n\
+             \x20 <graph id=\"bags\" edgedefault=\"directed\">\n",
+        );
+        for idx in self.graph.node_indices() {
+            writeln!(
+                res,
+                "    <node id=\"n{}\"><data key=\"colour\">{}</data><data key=\"nhl\">{}</data></node>",
+                idx.index(),
+                xml_escape(self.name(idx)),
+                hl.contains(&idx)
+            )?;
+        }
+        for e in self.graph.edge_references() {
+            writeln!(
+                res,
+                "    <edge source=\"n{}\" target=\"n{}\"><data key=\"weight\">{}</data><data key=\"ehl\">{}</data></edge>",
+                e.target().index(),
+                e.source().index(),
+                e.weight(),
+                hl.contains(&e.target())
+            )?;
+        }
+        res.push_str("  </graph>\n</graphml>\n");
+        Ok(res)
+    }
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
 }
 
-fn count_all_contained(g: &Graph<String, usize>, idx: NodeIndex) -> usize {
-    // idea: bags_in_idx = sum_for_neighbours(edge*(1 + count_all_contained(g, neighbour))))
-    g.neighbors_directed(idx, Direction::Incoming)
-        .fold(0, |res, i| {
-            res + g.edges_connecting(i, idx).next().unwrap().weight()
-                * (1 + count_all_contained(&g, i))
-        })
+#[aoc(day7, part1)]
+pub fn part1(input: &BagRules) -> Result<usize, Box<dyn Error>> {
+    Ok(input.containers_of("shiny gold")?.len())
 }
 
 #[aoc(day7, part2)]
-pub fn part2(input: &(Graph<String, usize>, HashMap<String, NodeIndex>)) -> usize {
-    let (g, hm) = input;
-    let bagidx = hm["shiny gold"];
-    count_all_contained(&g, bagidx)
+pub fn part2(input: &BagRules) -> Result<usize, Box<dyn Error>> {
+    input.contents_count("shiny gold")
 }
 
 #[cfg(test)]
@@ -118,22 +451,104 @@ dark violet bags contain no other bags.";
 
     #[test]
     pub fn test_generator() {
-        assert_eq!(true, true);
-        let (_graph, _hm) = input_generator(&INPUT);
+        let rules = input_generator(INPUT);
+        assert_eq!(rules.colours().count(), 7);
+        assert_eq!(rules.contents_count("vibrant purple").unwrap(), 12);
     }
 
     #[test]
     pub fn test_part1() {
-        assert_eq!(part1(&input_generator(&INPUT2)), 4);
+        assert_eq!(part1(&input_generator(INPUT2)).unwrap(), 4);
     }
 
     #[test]
     pub fn test_part2_1() {
-        assert_eq!(part2(&input_generator(&INPUT3)), 126);
+        assert_eq!(part2(&input_generator(INPUT3)).unwrap(), 126);
     }
 
     #[test]
     pub fn test_part2_2() {
-        assert_eq!(part2(&input_generator(&INPUT2)), 32);
+        assert_eq!(part2(&input_generator(INPUT2)).unwrap(), 32);
+    }
+
+    #[test]
+    pub fn test_unknown_colour() {
+        let rules = input_generator(INPUT2);
+        assert!(rules.containers_of("plaid tartan").is_err());
+        assert!(rules.contents_count("plaid tartan").is_err());
+    }
+
+    #[test]
+    pub fn test_bill_of_materials() {
+        let rules = input_generator(INPUT2);
+        let bom = rules.bill_of_materials("shiny gold").unwrap();
+        assert_eq!(bom["dark olive"], 1);
+        assert_eq!(bom["vibrant plum"], 2);
+        assert_eq!(bom["faded blue"], 13);
+        assert_eq!(bom["dotted black"], 16);
+        assert_eq!(bom.values().sum::<usize>(), 32);
+    }
+
+    #[test]
+    pub fn test_containment_path() {
+        let rules = input_generator(INPUT2);
+        assert_eq!(
+            rules
+                .containment_path("light red", "faded blue", PathKind::Shortest)
+                .unwrap(),
+            Some(vec!["light red", "muted yellow", "faded blue"])
+        );
+        assert_eq!(
+            rules
+                .containment_path("light red", "faded blue", PathKind::Longest)
+                .unwrap(),
+            Some(vec![
+                "light red",
+                "bright white",
+                "shiny gold",
+                "dark olive",
+                "faded blue"
+            ])
+        );
+        assert_eq!(
+            rules
+                .containment_path("faded blue", "light red", PathKind::Shortest)
+                .unwrap(),
+            None
+        );
+    }
+
+    const INPUT4: &str = "light red bags contain 1 dark red bag, 2 shiny gold bags.
+dark red bags contain 1 light red bag.
+shiny gold bags contain 1 shiny gold bag, 3 faded blue bags.";
+
+    #[test]
+    pub fn test_validate() {
+        assert!(input_generator(INPUT3).validate().is_ok());
+        let report = input_generator(INPUT4).validate();
+        assert_eq!(
+            report.cycles,
+            vec![vec![String::from("dark red"), String::from("light red")]]
+        );
+        assert_eq!(report.self_containing, vec![String::from("shiny gold")]);
+        assert_eq!(report.undefined, vec![String::from("faded blue")]);
+        assert!(input_generator(INPUT4).contents_count("light red").is_err());
+    }
+
+    #[test]
+    pub fn test_export() {
+        let rules = input_generator(INPUT2);
+        let dot = rules.to_dot(Some("dark olive")).unwrap();
+        assert!(dot.starts_with("digraph bags {"));
+        assert_eq!(dot.matches(" -> ").count(), 13);
+        // dark olive, faded blue and dotted black, plus the two edges between them
+        assert_eq!(dot.matches(", color=red").count(), 5);
+        assert!(rules.to_dot(Some("plaid tartan")).is_err());
+
+        let graphml = rules.to_graphml(None).unwrap();
+        assert_eq!(graphml.matches("<node ").count(), 9);
+        assert_eq!(graphml.matches("<edge ").count(), 13);
+        assert!(!graphml.contains(">true<"));
+        assert!(graphml.contains("<data key=\"weight\">9</data>"));
     }
 }
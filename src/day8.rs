@@ -1,88 +1,401 @@
 use aoc_runner_derive::{aoc, aoc_generator};
-use std::collections::HashSet;
+use simple_error::*;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
 
-#[derive(Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Op {
+    Acc,
+    Jmp,
+    Nop,
+}
+
+impl FromStr for Op {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "acc" => Ok(Op::Acc),
+            "jmp" => Ok(Op::Jmp),
+            "nop" => Ok(Op::Nop),
+            _ => bail!("unknown operation: {}", s),
+        }
+    }
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Op::Acc => write!(f, "acc"),
+            Op::Jmp => write!(f, "jmp"),
+            Op::Nop => write!(f, "nop"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Instruction {
-    op: String,
-    arg: i32,
+    pub op: Op,
+    pub arg: i32,
+}
+
+impl FromStr for Instruction {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Split by space, parse out:
+        // - operation
+        // - signed operand
+        // Map onto Instruction structure
+        let mut lsp = s.split_whitespace();
+        let op = lsp.next().ok_or("empty instruction")?.parse::<Op>()?;
+        let arg = match lsp.next() {
+            Some(a) => a.parse::<i32>()?,
+            None => bail!("missing argument in: {}", s),
+        };
+        if lsp.next().is_some() {
+            bail!("trailing garbage in: {}", s);
+        }
+        Ok(Instruction { op, arg })
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {:+}", self.op, self.arg)
+    }
+}
+
+// How a run of the machine ended
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExecResult {
+    // pc landed right after the last instruction
+    Terminated,
+    // pc came back to an instruction that had already run, after cycle instructions were executed
+    Looped { pc: usize, cycle: usize },
+    // pc jumped anywhere else outside the program
+    OutOfBounds { pc: i64 },
+}
+
+// One executed instruction: where it was, what it was and the accumulator right after it ran
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TraceEntry {
+    pub cycle: usize,
+    pub pc: usize,
+    pub instr: Instruction,
+    pub acc: i64,
+}
+
+// pc and acc are i64: a jump from anywhere by any i32 stays in range, and every run executes
+// each instruction at most once, so the accumulator cannot overflow either
+#[derive(Debug, Clone)]
+pub struct Machine {
+    program: Vec<Instruction>,
+    pc: i64,
+    acc: i64,
+    cycle: usize,
+    visited: Vec<bool>,
+    trace: Option<Vec<TraceEntry>>,
+}
+
+impl Machine {
+    pub fn new(program: &[Instruction]) -> Machine {
+        Machine {
+            program: program.to_vec(),
+            pc: 0,
+            acc: 0,
+            cycle: 0,
+            visited: vec![false; program.len()],
+            trace: None,
+        }
+    }
+
+    // Record every executed instruction from now on
+    pub fn with_trace(mut self) -> Machine {
+        self.trace = Some(Vec::new());
+        self
+    }
+
+    pub fn acc(&self) -> i64 {
+        self.acc
+    }
+
+    pub fn pc(&self) -> i64 {
+        self.pc
+    }
+
+    pub fn cycle(&self) -> usize {
+        self.cycle
+    }
+
+    pub fn program(&self) -> &[Instruction] {
+        &self.program
+    }
+
+    pub fn trace(&self) -> Option<&[TraceEntry]> {
+        self.trace.as_deref()
+    }
+
+    // Some(result) if the machine cannot execute anything from where it is
+    pub fn status(&self) -> Option<ExecResult> {
+        let len = self.program.len() as i64;
+        if self.pc == len {
+            Some(ExecResult::Terminated)
+        } else if self.pc < 0 || self.pc > len {
+            Some(ExecResult::OutOfBounds { pc: self.pc })
+        } else if self.visited[self.pc as usize] {
+            Some(ExecResult::Looped {
+                pc: self.pc as usize,
+                cycle: self.cycle,
+            })
+        } else {
+            None
+        }
+    }
+
+    // Execute one instruction; if the machine has already stopped, nothing runs and the reason is returned
+    pub fn step(&mut self) -> Option<ExecResult> {
+        if let Some(res) = self.status() {
+            return Some(res);
+        }
+        let pc = self.pc as usize;
+        let instr = self.program[pc];
+        self.visited[pc] = true;
+        match instr.op {
+            Op::Acc => {
+                self.acc += i64::from(instr.arg);
+                self.pc += 1;
+            }
+            Op::Jmp => self.pc += i64::from(instr.arg),
+            Op::Nop => self.pc += 1,
+        };
+        if let Some(trace) = self.trace.as_mut() {
+            trace.push(TraceEntry {
+                cycle: self.cycle,
+                pc,
+                instr,
+                acc: self.acc,
+            });
+        }
+        self.cycle += 1;
+        None
+    }
+
+    // Replace the instruction at idx, returning the one that was there
+    pub fn patch(&mut self, idx: usize, instr: Instruction) -> Result<Instruction, Box<dyn Error>> {
+        match self.program.get_mut(idx) {
+            Some(old) => Ok(std::mem::replace(old, instr)),
+            None => bail!("no instruction at {}", idx),
+        }
+    }
+
+    // Go back to the state right before the given cycle ran, replaying the registers from the trace
+    pub fn rewind(&mut self, cycle: usize) -> Result<(), Box<dyn Error>> {
+        let trace = match self.trace.as_mut() {
+            Some(t) => t,
+            None => bail!("cannot rewind without a trace"),
+        };
+        if cycle > trace.len() {
+            bail!("cycle {} has not run yet", cycle);
+        }
+        if cycle < trace.len() {
+            self.pc = trace[cycle].pc as i64;
+            self.acc = if cycle == 0 { 0 } else { trace[cycle - 1].acc };
+            trace.truncate(cycle);
+            self.visited.iter_mut().for_each(|v| *v = false);
+            let visited = &mut self.visited;
+            trace.iter().for_each(|t| visited[t.pc] = true);
+            self.cycle = cycle;
+        }
+        Ok(())
+    }
+
+    pub fn run(&mut self) -> ExecResult {
+        loop {
+            if let Some(res) = self.step() {
+                return res;
+            }
+        }
+    }
 }
 
 #[aoc_generator(day8)]
-pub fn input_generator(input: &str) -> Vec<Instruction> {
+pub fn input_generator(input: &str) -> Result<Vec<Instruction>, Box<dyn Error>> {
     input
         .lines()
-        .map(|l| {
-            // Split by space, parse out:
-            // - operation
-            // - signed operand
-            // Map onto Instruction structure
-            let mut lsp = l.split(" ");
-            let op = String::from(lsp.next().unwrap());
-            let arg = lsp.next().unwrap().parse::<i32>().unwrap();
-            Instruction { op, arg }
-        })
-        .collect::<Vec<Instruction>>()
+        .map(|l| l.parse::<Instruction>())
+        .collect::<Result<Vec<Instruction>, Box<dyn Error>>>()
 }
 
 // This function simulates code execution and returns accumulator and pc at the end
-pub fn simulate(input: &Vec<Instruction>) -> (i32, i32) {
-    let mut pc: i32 = 0;
-    let mut acc: i32 = 0;
-    let mut hs = HashSet::new();
-    let vl = input.len() as i32;
-    while pc >= 0 && pc < vl && !hs.contains(&pc) {
-        //eprintln!("PC: {} OP: {} ARG: {} ACC: {}", pc, instr.op, instr.arg, acc);
-        let instr = &input[pc as usize];
-        hs.insert(pc);
-        match instr.op.as_str() {
-            "acc" => {
-                acc = acc + instr.arg;
-                pc = pc + 1
-            }
-            "jmp" => pc = pc + instr.arg,
-            "nop" => pc = pc + 1,
-            _ => panic!("Not supposed to happen"),
-        };
-    }
-    // Run a match on operation and act on operand
-    (acc, pc)
+pub fn simulate(input: &[Instruction]) -> (i64, i64) {
+    let mut m = Machine::new(input);
+    m.run();
+    (m.acc(), m.pc())
 }
 
 #[aoc(day8, part1)]
-pub fn part1(input: &Vec<Instruction>) -> i32 {
+pub fn part1(input: &[Instruction]) -> i64 {
     simulate(input).0
 }
 
-#[aoc(day8, part2)]
-pub fn part2(input: &Vec<Instruction>) -> i32 {
-    // Idea: generate a vec of vecs of instructions where only one jmp or nop are altered
-    // Then run simulation on a generated vector
-    input
-        .iter()
-        .enumerate()
-        .fold(0, |ac, (idx, instr)| match instr.op.as_str() {
-            "jmp" => {
-                let mut newvec = input.to_vec();
-                newvec[idx].op = String::from("nop");
-                let (acc, pc) = simulate(&newvec);
-                if pc >= newvec.len() as i32 {
-                    ac + acc
-                } else {
-                    ac
-                }
+// Where control goes after instruction idx runs with the given op
+// Some(len) is termination, None is a jump anywhere else outside the program
+fn successor(idx: usize, op: Op, arg: i32, len: usize) -> Option<usize> {
+    let next = match op {
+        Op::Jmp => idx as i64 + arg as i64,
+        Op::Acc | Op::Nop => idx as i64 + 1,
+    };
+    if next >= 0 && next <= len as i64 {
+        Some(next as usize)
+    } else {
+        None
+    }
+}
+
+// The control-flow graph of a program
+// Every instruction has exactly one successor, so it is just a vector, plus the reverse edges
+// Node len stands for "terminated"
+pub struct ControlFlow {
+    succ: Vec<Option<usize>>,
+    pred: Vec<Vec<usize>>,
+    reaches_end: Vec<bool>,
+}
+
+impl ControlFlow {
+    pub fn new(program: &[Instruction]) -> ControlFlow {
+        let len = program.len();
+        let succ = program
+            .iter()
+            .enumerate()
+            .map(|(idx, instr)| successor(idx, instr.op, instr.arg, len))
+            .collect::<Vec<Option<usize>>>();
+        let mut pred = vec![Vec::new(); len + 1];
+        succ.iter().enumerate().for_each(|(idx, s)| {
+            if let Some(s) = s {
+                pred[*s].push(idx);
             }
-            "nop" => {
-                let mut newvec = input.to_vec();
-                newvec[idx].op = String::from("jmp");
-                let (acc, pc) = simulate(&newvec);
-                if pc >= newvec.len() as i32 {
-                    ac + acc
-                } else {
-                    ac
+        });
+
+        // Walk the reverse edges back from termination; whatever we meet gets there eventually
+        let mut reaches_end = vec![false; len + 1];
+        reaches_end[len] = true;
+        let mut stack = vec![len];
+        while let Some(n) = stack.pop() {
+            pred[n].iter().for_each(|&p| {
+                if !reaches_end[p] {
+                    reaches_end[p] = true;
+                    stack.push(p);
                 }
+            });
+        }
+
+        ControlFlow {
+            succ,
+            pred,
+            reaches_end,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.succ.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.succ.is_empty()
+    }
+
+    pub fn successor(&self, idx: usize) -> Option<usize> {
+        self.succ[idx]
+    }
+
+    pub fn predecessors(&self, idx: usize) -> &[usize] {
+        &self.pred[idx]
+    }
+
+    // Whether execution starting at idx terminates; idx == len() is termination itself
+    pub fn reaches_end(&self, idx: usize) -> bool {
+        self.reaches_end[idx]
+    }
+
+    // The instructions actually executed from the start, in order, until the run stops
+    pub fn execution_path(&self) -> Vec<usize> {
+        let mut seen = vec![false; self.len()];
+        let mut path = Vec::new();
+        let mut cur = Some(0);
+        while let Some(idx) = cur {
+            if idx >= self.len() || seen[idx] {
+                break;
+            }
+            seen[idx] = true;
+            path.push(idx);
+            cur = self.succ[idx];
+        }
+        path
+    }
+}
+
+// A single jmp <-> nop swap that makes a program terminate
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Repair {
+    pub index: usize,
+    pub op: Op,
+    // false if more than one swap would do
+    pub unique: bool,
+    // the accumulator once the patched program terminates
+    pub acc: i64,
+}
+
+// Only an instruction the broken program actually executes can change where it goes,
+// and swapping it fixes the program exactly when the new successor reaches termination.
+// Both the path and reaches_end are linear, so this is O(n) overall
+pub fn repair(program: &[Instruction]) -> Option<Repair> {
+    let cf = ControlFlow::new(program);
+    if cf.reaches_end(0) {
+        return None;
+    }
+    let fixes = cf
+        .execution_path()
+        .into_iter()
+        .filter_map(|idx| {
+            let instr = program[idx];
+            let op = match instr.op {
+                Op::Jmp => Op::Nop,
+                Op::Nop => Op::Jmp,
+                Op::Acc => return None,
+            };
+            match successor(idx, op, instr.arg, program.len()) {
+                Some(s) if cf.reaches_end(s) => Some((idx, op)),
+                _ => None,
             }
-            _ => ac,
         })
+        .collect::<Vec<(usize, Op)>>();
+
+    fixes.first().map(|&(index, op)| {
+        let mut m = Machine::new(program);
+        m.program[index].op = op;
+        m.run();
+        Repair {
+            index,
+            op,
+            unique: fixes.len() == 1,
+            acc: m.acc(),
+        }
+    })
+}
+
+#[aoc(day8, part2)]
+pub fn part2(input: &[Instruction]) -> Result<i64, Box<dyn Error>> {
+    match repair(input) {
+        Some(r) if r.unique => Ok(r.acc),
+        Some(r) => bail!(
+            "more than one fix works, the first swaps {} to {}",
+            r.index,
+            r.op
+        ),
+        None => bail!("no single swap makes the program terminate"),
+    }
 }
 
 #[cfg(test)]
@@ -99,21 +412,140 @@ acc +1
 jmp -4
 acc +6";
 
-    /*     #[test]
-       pub fn test_generator() {
-           assert_eq!(
-               input_generator(INPUT),
-               vec![String::from("1"), String::from("2"), String::from("3")]
-           );
-       }
-    */
+    #[test]
+    pub fn test_generator() {
+        let program = input_generator(INPUT).unwrap();
+        assert_eq!(program.len(), 9);
+        assert_eq!(
+            program[4],
+            Instruction {
+                op: Op::Jmp,
+                arg: -3
+            }
+        );
+        assert_eq!(program[3].to_string(), "acc +3");
+        assert!(input_generator("mul +2").is_err());
+        assert!(input_generator("acc").is_err());
+    }
+
+    #[test]
+    pub fn test_machine() {
+        let program = input_generator(INPUT).unwrap();
+        let mut m = Machine::new(&program);
+        assert_eq!(m.run(), ExecResult::Looped { pc: 1, cycle: 7 });
+        assert_eq!(m.acc(), 5);
+        assert!(m.trace().is_none());
+
+        let mut m = Machine::new(&input_generator("jmp +2\nacc +1\njmp -5").unwrap());
+        assert_eq!(m.run(), ExecResult::OutOfBounds { pc: -3 });
+
+        // Neither register wraps around at the ends of the i32 range
+        let mut m = Machine::new(&input_generator("nop +0\njmp +2147483647").unwrap());
+        assert_eq!(m.run(), ExecResult::OutOfBounds { pc: 2_147_483_648 });
+        let mut m = Machine::new(&input_generator("acc +2147483647\nacc +1").unwrap());
+        assert_eq!(m.run(), ExecResult::Terminated);
+        assert_eq!(m.acc(), 2_147_483_648);
+    }
+
+    #[test]
+    pub fn test_trace() {
+        let mut program = input_generator(INPUT).unwrap();
+        program[7].op = Op::Nop;
+        let mut m = Machine::new(&program).with_trace();
+        assert_eq!(m.run(), ExecResult::Terminated);
+        assert_eq!(m.acc(), 8);
+        let trace = m.trace().unwrap();
+        assert_eq!(
+            trace.iter().map(|t| t.pc).collect::<Vec<usize>>(),
+            vec![0, 1, 2, 6, 7, 8]
+        );
+        assert_eq!(
+            trace[3],
+            TraceEntry {
+                cycle: 3,
+                pc: 6,
+                instr: Instruction {
+                    op: Op::Acc,
+                    arg: 1
+                },
+                acc: 2
+            }
+        );
+    }
+
     #[test]
     pub fn test_part1() {
-        assert_eq!(part1(&input_generator(&INPUT)), 5);
+        assert_eq!(part1(&input_generator(INPUT).unwrap()), 5);
     }
 
     #[test]
     pub fn test_part2() {
-        assert_eq!(part2(&input_generator(&INPUT)), 8);
+        assert_eq!(part2(&input_generator(INPUT).unwrap()).unwrap(), 8);
+    }
+
+    #[test]
+    pub fn test_patch_and_rewind() {
+        let program = input_generator(INPUT).unwrap();
+        let mut m = Machine::new(&program).with_trace();
+        m.run();
+        assert_eq!(m.cycle(), 7);
+        m.rewind(4).unwrap();
+        assert_eq!((m.pc(), m.acc(), m.cycle()), (7, 2, 4));
+        assert_eq!(m.trace().unwrap().len(), 4);
+        assert_eq!(
+            m.patch(
+                7,
+                Instruction {
+                    op: Op::Nop,
+                    arg: -4
+                }
+            )
+            .unwrap(),
+            Instruction {
+                op: Op::Jmp,
+                arg: -4
+            }
+        );
+        assert_eq!(m.run(), ExecResult::Terminated);
+        assert_eq!(m.acc(), 8);
+        assert!(m.rewind(9).is_err());
+        assert!(m.patch(9, program[0]).is_err());
+        assert!(Machine::new(&program).rewind(0).is_err());
+    }
+
+    #[test]
+    pub fn test_control_flow() {
+        let cf = ControlFlow::new(&input_generator(INPUT).unwrap());
+        assert_eq!(cf.execution_path(), vec![0, 1, 2, 6, 7, 3, 4]);
+        assert_eq!(
+            (0..=9)
+                .filter(|&i| cf.reaches_end(i))
+                .collect::<Vec<usize>>(),
+            vec![8, 9]
+        );
+        assert_eq!(cf.predecessors(3), &[7]);
+    }
+
+    #[test]
+    pub fn test_repair() {
+        let program = input_generator(INPUT).unwrap();
+        assert_eq!(
+            repair(&program),
+            Some(Repair {
+                index: 7,
+                op: Op::Nop,
+                unique: true,
+                acc: 8
+            })
+        );
+
+        // Already terminates, nothing to repair
+        assert_eq!(repair(&input_generator("acc +1\nnop +0").unwrap()), None);
+
+        // Both the nop at 0 and the jmp at 1 lead out
+        let ambiguous = input_generator("nop +2\njmp -1\njmp +1").unwrap();
+        let r = repair(&ambiguous).unwrap();
+        assert!(!r.unique);
+        assert!(part2(&ambiguous).is_err());
     }
 }
@@ -0,0 +1,296 @@
+use crate::day8::{ControlFlow, Instruction, Op};
+use simple_error::*;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+// A friendlier text format for the handheld programs:
+//
+//   # comments start with '#' or ';' and run to the end of the line
+//   loop_start:
+//       acc +1
+//       jmp loop_start   ; same as jmp -1
+//
+// A label names the next instruction, or the end of the program if nothing follows it.
+// Plain day8 input is valid as it is, so it assembles to the same program as input_generator
+
+fn strip_comment(l: &str) -> &str {
+    match l.find(['#', ';']) {
+        Some(i) => &l[..i],
+        None => l,
+    }
+}
+
+fn is_label(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+// Split a line into an optional "label:" and the instruction text after it
+fn split_label(l: &str) -> (Option<&str>, &str) {
+    match l.find(':') {
+        Some(i) => (Some(l[..i].trim()), l[i + 1..].trim()),
+        None => (None, l),
+    }
+}
+
+pub fn assemble(source: &str) -> Result<Vec<Instruction>, Box<dyn Error>> {
+    // First pass: find where every label points and keep the instruction lines
+    let mut labels: HashMap<&str, usize> = HashMap::new();
+    let mut lines: Vec<(usize, &str)> = Vec::new();
+    for (n, l) in source.lines().enumerate() {
+        let (label, rest) = split_label(strip_comment(l).trim());
+        if let Some(label) = label {
+            if !is_label(label) {
+                bail!("line {}: bad label '{}'", n + 1, label);
+            }
+            if labels.insert(label, lines.len()).is_some() {
+                bail!("line {}: label '{}' defined twice", n + 1, label);
+            }
+        }
+        if !rest.is_empty() {
+            lines.push((n + 1, rest));
+        }
+    }
+
+    // Second pass: parse instructions, turning label arguments into relative offsets
+    lines
+        .iter()
+        .enumerate()
+        .map(|(idx, (n, l))| {
+            let mut words = l.split_whitespace();
+            let op = match words.next().map(|w| w.parse::<Op>()) {
+                Some(Ok(op)) => op,
+                Some(Err(e)) => bail!("line {}: {}", n, e),
+                None => bail!("line {}: empty instruction", n),
+            };
+            let arg = match words.next() {
+                Some(a) if is_label(a) => {
+                    if op == Op::Acc {
+                        bail!("line {}: acc takes a number, not label '{}'", n, a);
+                    }
+                    match labels.get(a) {
+                        Some(target) => *target as i32 - idx as i32,
+                        None => bail!("line {}: unknown label '{}'", n, a),
+                    }
+                }
+                Some(a) => match a.parse::<i32>() {
+                    Ok(arg) => arg,
+                    Err(e) => bail!("line {}: bad argument '{}': {}", n, a, e),
+                },
+                None => bail!("line {}: missing argument", n),
+            };
+            if let Some(w) = words.next() {
+                bail!("line {}: unexpected '{}'", n, w);
+            }
+            Ok(Instruction { op, arg })
+        })
+        .collect()
+}
+
+// The inverse of assemble: every in-range jmp target gets a label named after its index
+pub fn disassemble(program: &[Instruction]) -> String {
+    let len = program.len() as i64;
+    let mut targets = vec![false; program.len() + 1];
+    program.iter().enumerate().for_each(|(idx, instr)| {
+        let t = idx as i64 + instr.arg as i64;
+        if instr.op == Op::Jmp && t >= 0 && t <= len {
+            targets[t as usize] = true;
+        }
+    });
+
+    let mut res = String::new();
+    for (idx, instr) in program.iter().enumerate() {
+        if targets[idx] {
+            res.push_str(&format!("L{}:\n", idx));
+        }
+        let t = idx as i64 + instr.arg as i64;
+        if instr.op == Op::Jmp && t >= 0 && t <= len {
+            res.push_str(&format!("    {} L{}\n", instr.op, t));
+        } else {
+            res.push_str(&format!("    {}\n", instr));
+        }
+    }
+    if targets[program.len()] {
+        res.push_str(&format!("L{}:\n", program.len()));
+    }
+    res
+}
+
+// What the static checker can find before a program runs
+#[derive(Debug, Clone, PartialEq)]
+pub enum Diagnostic {
+    // A jmp that leaves the program anywhere but right after the last instruction
+    JumpOutOfRange { index: usize, target: i64 },
+    // Instructions from..=to can never execute
+    Unreachable { from: usize, to: usize },
+    // Execution is bound to loop forever: the instruction at from sends it back to to
+    InfiniteLoop { from: usize, to: usize },
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Diagnostic::JumpOutOfRange { index, target } => {
+                write!(f, "{}: jump out of range to {}", index, target)
+            }
+            Diagnostic::Unreachable { from, to } if from == to => {
+                write!(f, "{}: unreachable", from)
+            }
+            Diagnostic::Unreachable { from, to } => write!(f, "{}-{}: unreachable", from, to),
+            Diagnostic::InfiniteLoop { from, to } => {
+                write!(f, "{}: infinite loop back to {}", from, to)
+            }
+        }
+    }
+}
+
+// Programs take no input, so a single walk from the start decides both reachability and looping
+pub fn check(program: &[Instruction]) -> Vec<Diagnostic> {
+    let len = program.len() as i64;
+    let mut res = program
+        .iter()
+        .enumerate()
+        .filter_map(|(index, instr)| {
+            let target = index as i64 + instr.arg as i64;
+            if instr.op == Op::Jmp && (target < 0 || target > len) {
+                Some(Diagnostic::JumpOutOfRange { index, target })
+            } else {
+                None
+            }
+        })
+        .collect::<Vec<Diagnostic>>();
+
+    let cf = ControlFlow::new(program);
+    let path = cf.execution_path();
+    let mut reachable = vec![false; program.len()];
+    path.iter().for_each(|&idx| reachable[idx] = true);
+
+    let mut idx = 0;
+    while idx < program.len() {
+        if reachable[idx] {
+            idx += 1;
+            continue;
+        }
+        let from = idx;
+        while idx < program.len() && !reachable[idx] {
+            idx += 1;
+        }
+        res.push(Diagnostic::Unreachable { from, to: idx - 1 });
+    }
+
+    if let Some(&last) = path.last() {
+        if let Some(next) = cf.successor(last) {
+            if next < program.len() {
+                res.push(Diagnostic::InfiniteLoop {
+                    from: last,
+                    to: next,
+                });
+            }
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::day8::input_generator;
+
+    const INPUT: &str = "nop +0
+acc +1
+jmp +4
+acc +3
+jmp -3
+acc -99
+acc +1
+jmp -4
+acc +6";
+
+    const SOURCE: &str = "# the example program, with labels
+start:
+    nop +0
+    acc +1
+    jmp skip    ; over the -99
+back:
+    acc +3
+    jmp start_acc
+    acc -99
+skip: acc +1
+    jmp back
+    acc +6
+";
+
+    #[test]
+    pub fn test_assemble() {
+        let source = SOURCE.replace("jmp start_acc", "jmp -3");
+        assert_eq!(assemble(&source).unwrap(), input_generator(INPUT).unwrap());
+        assert_eq!(assemble(INPUT).unwrap(), input_generator(INPUT).unwrap());
+    }
+
+    #[test]
+    pub fn test_assemble_errors() {
+        assert_eq!(
+            assemble(SOURCE).unwrap_err().to_string(),
+            "line 8: unknown label 'start_acc'"
+        );
+        assert!(assemble("a:\na:\nnop +0").is_err());
+        assert!(assemble("x:\nacc x").is_err());
+        assert!(assemble("jmp +1 +2").is_err());
+        assert!(assemble("1abc: nop +0").is_err());
+    }
+
+    #[test]
+    pub fn test_disassemble() {
+        let program = input_generator("jmp +2\nacc +1\njmp -2\njmp +7\nnop +1\njmp +0").unwrap();
+        let text = disassemble(&program);
+        assert_eq!(
+            text,
+            "L0:
+    jmp L2
+    acc +1
+L2:
+    jmp L0
+    jmp +7
+    nop +1
+L5:
+    jmp L5
+"
+        );
+        assert_eq!(assemble(&text).unwrap(), program);
+        let program = input_generator(INPUT).unwrap();
+        assert_eq!(assemble(&disassemble(&program)).unwrap(), program);
+    }
+
+    #[test]
+    pub fn test_check() {
+        assert_eq!(
+            check(&input_generator(INPUT).unwrap()),
+            vec![
+                Diagnostic::Unreachable { from: 5, to: 5 },
+                Diagnostic::Unreachable { from: 8, to: 8 },
+                Diagnostic::InfiniteLoop { from: 4, to: 1 },
+            ]
+        );
+        let program = input_generator("jmp +2\nacc +1\njmp -5\nacc +3").unwrap();
+        let diags = check(&program);
+        assert_eq!(
+            diags,
+            vec![
+                Diagnostic::JumpOutOfRange {
+                    index: 2,
+                    target: -3
+                },
+                Diagnostic::Unreachable { from: 1, to: 1 },
+                Diagnostic::Unreachable { from: 3, to: 3 },
+            ]
+        );
+        assert_eq!(diags[0].to_string(), "2: jump out of range to -3");
+        assert!(check(&input_generator("acc +1\njmp +2\nacc +2").unwrap())
+            .iter()
+            .all(|d| matches!(d, Diagnostic::Unreachable { .. })));
+    }
+}
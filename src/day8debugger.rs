@@ -0,0 +1,369 @@
+use crate::day8::{ExecResult, Instruction, Machine, Op};
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::io::{BufRead, Write};
+
+// A line-oriented debugger over the day8 machine
+// It only reads commands from a BufRead and writes to a Write, so a plain terminal,
+// a pipe or a test string all drive it the same way
+
+const HELP: &str = "commands:
+  s, step [n]          execute n instructions (default 1)
+  c, continue          run until a breakpoint, a watch or the end
+  b, break <pc|op>     stop before the instruction at pc, or before any acc/jmp/nop
+  d, delete <pc|op>    remove a breakpoint
+  w, watch             toggle stopping whenever acc changes
+  l, list [n]          show n instructions around pc (default 3)
+  p, print             show pc, acc and cycle
+  patch <idx> <instr>  replace an instruction, e.g. patch 7 nop -4
+  r, rewind <cycle>    go back to right before the given cycle ran
+  t, trace             show every executed instruction
+  h, help              show this text
+  q, quit              leave the debugger";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Breakpoint {
+    Pc(usize),
+    Op(Op),
+}
+
+impl Breakpoint {
+    fn parse(s: &str) -> Result<Breakpoint, Box<dyn Error>> {
+        match s.parse::<usize>() {
+            Ok(pc) => Ok(Breakpoint::Pc(pc)),
+            Err(_) => Ok(Breakpoint::Op(s.parse::<Op>()?)),
+        }
+    }
+}
+
+pub struct Debugger {
+    machine: Machine,
+    breakpoints: HashSet<Breakpoint>,
+    watch: bool,
+}
+
+impl Debugger {
+    pub fn new(program: &[Instruction]) -> Debugger {
+        Debugger {
+            machine: Machine::new(program).with_trace(),
+            breakpoints: HashSet::new(),
+            watch: false,
+        }
+    }
+
+    pub fn machine(&self) -> &Machine {
+        &self.machine
+    }
+
+    // Read commands until quit or end of input
+    pub fn run<R: BufRead, W: Write>(
+        &mut self,
+        input: R,
+        mut output: W,
+    ) -> Result<(), Box<dyn Error>> {
+        write!(output, "(dbg) ")?;
+        output.flush()?;
+        for line in input.lines() {
+            let line = line?;
+            let mut words = line.split_whitespace();
+            let cmd = words.next().unwrap_or("");
+            let args = words.collect::<Vec<&str>>();
+            if cmd == "q" || cmd == "quit" {
+                break;
+            }
+            if let Err(e) = self.command(cmd, &args, &mut output) {
+                writeln!(output, "error: {}", e)?;
+            }
+            write!(output, "(dbg) ")?;
+            output.flush()?;
+        }
+        writeln!(output)?;
+        Ok(())
+    }
+
+    fn command<W: Write>(
+        &mut self,
+        cmd: &str,
+        args: &[&str],
+        out: &mut W,
+    ) -> Result<(), Box<dyn Error>> {
+        match cmd {
+            "" => {}
+            "s" | "step" => {
+                let n = match args.first() {
+                    Some(a) => a.parse::<usize>()?,
+                    None => 1,
+                };
+                for _ in 0..n {
+                    if let Some(res) = self.machine.step() {
+                        self.report(&res, out)?;
+                        return Ok(());
+                    }
+                }
+                self.print_state(out)?;
+            }
+            "c" | "continue" => self.resume(out)?,
+            "b" | "break" => {
+                for a in args {
+                    self.breakpoints.insert(Breakpoint::parse(a)?);
+                }
+                self.list_breakpoints(out)?;
+            }
+            "d" | "delete" => {
+                for a in args {
+                    if !self.breakpoints.remove(&Breakpoint::parse(a)?) {
+                        writeln!(out, "no breakpoint at {}", a)?;
+                    }
+                }
+                self.list_breakpoints(out)?;
+            }
+            "w" | "watch" => {
+                self.watch = !self.watch;
+                writeln!(
+                    out,
+                    "watch on acc: {}",
+                    if self.watch { "on" } else { "off" }
+                )?;
+            }
+            "l" | "list" => {
+                let n = match args.first() {
+                    Some(a) => a.parse::<usize>()?,
+                    None => 3,
+                };
+                self.list(n, out)?;
+            }
+            "p" | "print" => self.print_state(out)?,
+            "patch" => {
+                if args.len() < 2 {
+                    return Err("usage: patch <idx> <instr>".into());
+                }
+                let idx = args[0].parse::<usize>()?;
+                let instr = args[1..].join(" ").parse::<Instruction>()?;
+                let old = self.machine.patch(idx, instr)?;
+                writeln!(out, "{:>5}: {} -> {}", idx, old, instr)?;
+            }
+            "r" | "rewind" => {
+                let cycle = match args.first() {
+                    Some(a) => a.parse::<usize>()?,
+                    None => return Err("usage: rewind <cycle>".into()),
+                };
+                self.machine.rewind(cycle)?;
+                self.print_state(out)?;
+            }
+            "t" | "trace" => {
+                for t in self.machine.trace().unwrap_or(&[]) {
+                    writeln!(
+                        out,
+                        "[{:>5}] {:>5}: {:<10} acc = {}",
+                        t.cycle,
+                        t.pc,
+                        t.instr.to_string(),
+                        t.acc
+                    )?;
+                }
+            }
+            "h" | "help" => writeln!(out, "{}", HELP)?,
+            _ => return Err(format!("unknown command: {} (try help)", cmd).into()),
+        }
+        Ok(())
+    }
+
+    fn is_breakpoint(&self) -> bool {
+        let pc = self.machine.pc();
+        if pc < 0 || pc as usize >= self.machine.program().len() {
+            return false;
+        }
+        let pc = pc as usize;
+        self.breakpoints.contains(&Breakpoint::Pc(pc))
+            || self
+                .breakpoints
+                .contains(&Breakpoint::Op(self.machine.program()[pc].op))
+    }
+
+    // Always execute at least one instruction, so continuing from a breakpoint moves on
+    fn resume<W: Write>(&mut self, out: &mut W) -> Result<(), Box<dyn Error>> {
+        loop {
+            let acc = self.machine.acc();
+            if let Some(res) = self.machine.step() {
+                return self.report(&res, out);
+            }
+            if self.watch && acc != self.machine.acc() {
+                writeln!(out, "watch: acc {} -> {}", acc, self.machine.acc())?;
+                return self.print_state(out);
+            }
+            if self.is_breakpoint() {
+                writeln!(out, "breakpoint")?;
+                return self.print_state(out);
+            }
+        }
+    }
+
+    fn report<W: Write>(&self, res: &ExecResult, out: &mut W) -> Result<(), Box<dyn Error>> {
+        match res {
+            ExecResult::Terminated => writeln!(out, "terminated, acc = {}", self.machine.acc())?,
+            ExecResult::Looped { pc, cycle } => writeln!(
+                out,
+                "loop: pc {} runs again at cycle {}, acc = {}",
+                pc,
+                cycle,
+                self.machine.acc()
+            )?,
+            ExecResult::OutOfBounds { pc } => writeln!(
+                out,
+                "out of bounds: pc = {}, acc = {}",
+                pc,
+                self.machine.acc()
+            )?,
+        };
+        Ok(())
+    }
+
+    fn print_state<W: Write>(&self, out: &mut W) -> Result<(), Box<dyn Error>> {
+        let pc = self.machine.pc();
+        let next = if pc >= 0 {
+            self.machine.program().get(pc as usize)
+        } else {
+            None
+        };
+        match next {
+            Some(instr) => writeln!(
+                out,
+                "cycle {}, pc {}: {}, acc = {}",
+                self.machine.cycle(),
+                pc,
+                instr,
+                self.machine.acc()
+            )?,
+            None => writeln!(
+                out,
+                "cycle {}, pc {}: <outside program>, acc = {}",
+                self.machine.cycle(),
+                pc,
+                self.machine.acc()
+            )?,
+        };
+        Ok(())
+    }
+
+    fn list<W: Write>(&self, n: usize, out: &mut W) -> Result<(), Box<dyn Error>> {
+        let pc = self.machine.pc();
+        let len = self.machine.program().len() as i64;
+        // Any count past the ends of the program just lists all of it
+        let n = i64::try_from(n).unwrap_or(i64::MAX);
+        let from = pc.saturating_sub(n).max(0);
+        let to = pc.saturating_add(n).min(len - 1);
+        for idx in from..=to {
+            let marker = if idx == pc { "=>" } else { "  " };
+            let bp = if self.breakpoints.contains(&Breakpoint::Pc(idx as usize)) {
+                "*"
+            } else {
+                " "
+            };
+            writeln!(
+                out,
+                "{}{}{:>5}: {}",
+                marker,
+                bp,
+                idx,
+                self.machine.program()[idx as usize]
+            )?;
+        }
+        Ok(())
+    }
+
+    fn list_breakpoints<W: Write>(&self, out: &mut W) -> Result<(), Box<dyn Error>> {
+        let mut bps = self
+            .breakpoints
+            .iter()
+            .map(|b| match b {
+                Breakpoint::Pc(pc) => pc.to_string(),
+                Breakpoint::Op(op) => op.to_string(),
+            })
+            .collect::<Vec<String>>();
+        bps.sort();
+        writeln!(out, "breakpoints: {}", bps.join(", "))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::day8::input_generator;
+
+    const INPUT: &str = "nop +0
+acc +1
+jmp +4
+acc +3
+jmp -3
+acc -99
+acc +1
+jmp -4
+acc +6";
+
+    fn session(commands: &str) -> (Debugger, String) {
+        let mut dbg = Debugger::new(&input_generator(INPUT).unwrap());
+        let mut out = Vec::new();
+        dbg.run(commands.as_bytes(), &mut out).unwrap();
+        (dbg, String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    pub fn test_step_and_continue() {
+        let (dbg, out) = session("step 2\ncontinue\n");
+        assert!(out.contains("cycle 2, pc 2: jmp +4, acc = 1"));
+        assert!(out.contains("loop: pc 1 runs again at cycle 7, acc = 5"));
+        assert_eq!(dbg.machine().cycle(), 7);
+    }
+
+    #[test]
+    pub fn test_breakpoints() {
+        let (dbg, out) = session("b 7 acc\nc\nc\nd acc\nc\nc\n");
+        assert!(out.contains("breakpoints: 7, acc"));
+        // first stop on the acc at pc 1, then on the breakpoint at pc 6, then pc 7
+        assert!(out.contains("breakpoint\ncycle 1, pc 1: acc +1, acc = 0"));
+        assert!(out.contains("breakpoint\ncycle 3, pc 6: acc +1, acc = 1"));
+        assert!(out.contains("breakpoint\ncycle 4, pc 7: jmp -4, acc = 2"));
+        assert!(out.contains("breakpoints: 7\n"));
+        assert!(out.contains("loop: pc 1 runs again at cycle 7, acc = 5"));
+        assert_eq!(dbg.machine().pc(), 1);
+    }
+
+    #[test]
+    pub fn test_watch() {
+        let (dbg, out) = session("watch\nc\nc\n");
+        assert!(out.contains("watch: acc 0 -> 1"));
+        assert!(out.contains("watch: acc 1 -> 2"));
+        assert_eq!(dbg.machine().acc(), 2);
+    }
+
+    #[test]
+    pub fn test_list_patch_rewind() {
+        let (dbg, out) = session("s 4\nlist 1\nrewind 4\npatch 7 nop -4\nc\n");
+        assert!(out.contains(concat!(
+            "       6: acc +1\n",
+            "=>     7: jmp -4\n",
+            "       8: acc +6\n"
+        )));
+        assert!(out.contains("    7: jmp -4 -> nop -4"));
+        assert!(out.contains("terminated, acc = 8"));
+        assert_eq!(dbg.machine().trace().unwrap().len(), 6);
+    }
+
+    #[test]
+    pub fn test_errors() {
+        let (_, out) = session("frobnicate\nb mul\npatch 99 nop +0\nrewind 3\nq\nstep\n");
+        assert!(out.contains("error: unknown command: frobnicate"));
+        assert!(out.contains("error: unknown operation: mul"));
+        assert!(out.contains("error: no instruction at 99"));
+        assert!(out.contains("error: cycle 3 has not run yet"));
+        // nothing runs after quit
+        assert!(!out.contains("cycle 1"));
+
+        let (_, out) = session("list 2147483648\nlist 18446744073709551615\n");
+        assert_eq!(out.matches("=>     0: nop +0").count(), 2);
+        assert_eq!(out.matches("    8: acc +6").count(), 2);
+        assert!(!out.contains("error"));
+    }
+}
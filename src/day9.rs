@@ -1,4 +1,5 @@
 use aoc_runner_derive::{aoc, aoc_generator};
+use std::collections::{HashMap, VecDeque};
 
 #[aoc_generator(day9)]
 pub fn input_generator(input: &str) -> Vec<u64> {
@@ -8,76 +9,130 @@ pub fn input_generator(input: &str) -> Vec<u64> {
         .collect::<Vec<u64>>()
 }
 
-// A C-way of doing things
-pub fn solver(input: &Vec<u64>, preamble: usize) -> u64 {
-    // start from the 6th element
-    // check whether the element can be the sum of any two of the previous 25 ones
-    // move on if it can, and stop and return if it cannot
-    let mut start: usize = 0; // start of slice to check
-    let mut end: usize = preamble - 1; // end of slice to check
-    let mut el: usize = preamble - 1; // the index of a value being checked for the sum; should start with end, as it is pre-incremented
-    let mut first: usize = start; // the first element of the sum
-    let mut second: usize = first + 1; // the second element of the sum
-    let mut found = true;
-
-    while el < input.len() && found {
-        el += 1;
-        found = false;
-        while first < end && !found {
-            while input[first] < input[el] && second <= end && !found {
-                if input[first] + input[second] == input[el] {
-                    found = true;
+pub const PREAMBLE: usize = 25;
+
+// Takes the numbers one at a time and remembers every one that is not a sum of two different
+// numbers among the preamble before it.
+// The window keeps a multiset of all pairwise sums, so checking a number is a single lookup,
+// and sliding the window costs one pass over it to drop the oldest number's sums and add the newest
+pub struct XmasValidator {
+    preamble: usize,
+    window: VecDeque<u64>,
+    sums: HashMap<u64, usize>,
+    index: usize,
+    invalid: Vec<(usize, u64)>,
+}
+
+impl XmasValidator {
+    pub fn new(preamble: usize) -> XmasValidator {
+        XmasValidator {
+            preamble,
+            window: VecDeque::with_capacity(preamble + 1),
+            sums: HashMap::new(),
+            index: 0,
+            invalid: Vec::new(),
+        }
+    }
+
+    // Returns whether the number is valid; the preamble itself always is
+    pub fn push(&mut self, n: u64) -> bool {
+        let valid = self.window.len() < self.preamble || self.sums.contains_key(&n);
+        if !valid {
+            self.invalid.push((self.index, n));
+        }
+        self.index += 1;
+
+        if self.preamble == 0 {
+            return valid;
+        }
+        if self.window.len() == self.preamble {
+            let old = self.window.pop_front().unwrap();
+            let sums = &mut self.sums;
+            self.window.iter().filter(|&&w| w != old).for_each(|&w| {
+                let sum = match old.checked_add(w) {
+                    Some(sum) => sum,
+                    None => return,
+                };
+                let count = sums.get_mut(&sum).unwrap();
+                *count -= 1;
+                if *count == 0 {
+                    sums.remove(&sum);
                 }
-                second += 1;
-            }
-            first += 1;
-            second = first + 1;
+            });
         }
-        start += 1;
-        end += 1;
-        first = start;
-        second = first + 1;
+        let sums = &mut self.sums;
+        // A sum past u64::MAX can never match a number, so such pairs are left out
+        self.window
+            .iter()
+            .filter(|&&w| w != n)
+            .filter_map(|&w| n.checked_add(w))
+            .for_each(|sum| *sums.entry(sum).or_insert(0) += 1);
+        self.window.push_back(n);
+        valid
     }
 
-    if el < input.len() {
-        input[el]
-    } else {
-        0
+    // Every invalid number seen so far, with its index in the stream
+    pub fn invalid(&self) -> &[(usize, u64)] {
+        &self.invalid
     }
 }
 
+pub fn find_invalid(input: &[u64], preamble: usize) -> Vec<(usize, u64)> {
+    let mut v = XmasValidator::new(preamble);
+    input.iter().for_each(|&n| {
+        v.push(n);
+    });
+    v.invalid().to_vec()
+}
+
+// The first invalid number, if any
+pub fn solver(input: &[u64], preamble: usize) -> Option<u64> {
+    let mut v = XmasValidator::new(preamble);
+    input.iter().copied().find(|&n| !v.push(n))
+}
+
 #[aoc(day9, part1)]
-pub fn part1(input: &Vec<u64>) -> u64 {
-    solver(input, 25)
+pub fn part1(input: &[u64]) -> Option<u64> {
+    solver(input, PREAMBLE)
 }
 
-pub fn solver2(input: &Vec<u64>, preamble: usize) -> u64 {
-    let num = solver(input, preamble);
+// The contiguous range of at least two numbers adding up to the first invalid number,
+// as (start, end, min, max) with both ends included
+pub fn solver2(input: &[u64], preamble: usize) -> Option<(usize, usize, u64, u64)> {
+    let num = solver(input, preamble)?;
+    if input.len() < 2 {
+        return None;
+    }
     let mut left: usize = 0;
     let mut right: usize = left + 1;
-    let mut found = false;
-
     let mut sum: u64 = input[0] + input[1];
 
-    while left < right && right < input.len() && !found {
-        //let sum: u64 = input[left..=right].iter().sum(); // 61.7 microseconds
-        if sum < num {
+    while right < input.len() {
+        if sum == num {
+            let range = &input[left..=right];
+            return Some((
+                left,
+                right,
+                *range.iter().min().unwrap(),
+                *range.iter().max().unwrap(),
+            ));
+        }
+        if sum < num || right - left == 1 {
             right += 1;
-            sum += input[right];
-        } else if sum > num {
+            if right < input.len() {
+                sum += input[right];
+            }
+        } else {
             sum -= input[left];
             left += 1;
-        } else {
-            found = true;
         }
     }
-
-    input[left..=right].iter().min().unwrap() + input[left..=right].iter().max().unwrap()
+    None
 }
 
 #[aoc(day9, part2)]
-pub fn part2(input: &Vec<u64>) -> u64 {
-    solver2(input, 25)
+pub fn part2(input: &[u64]) -> Option<u64> {
+    solver2(input, PREAMBLE).map(|(_, _, min, max)| min + max)
 }
 
 #[cfg(test)]
@@ -102,11 +157,35 @@ mod tests {
 
     #[test]
     pub fn test_part1() {
-        assert_eq!(solver(&input_generator(INPUT2), 5), 127);
+        assert_eq!(solver(&input_generator(INPUT2), 5), Some(127));
+        assert_eq!(solver(&input_generator(INPUT), 2), None);
     }
 
     #[test]
     pub fn test_part2() {
-        assert_eq!(solver2(&input_generator(INPUT2), 5), 62);
+        assert_eq!(solver2(&input_generator(INPUT2), 5), Some((2, 5, 15, 47)));
+        assert_eq!(solver2(&input_generator(INPUT), 2), None);
+    }
+
+    #[test]
+    pub fn test_validator() {
+        assert_eq!(find_invalid(&input_generator(INPUT2), 5), vec![(14, 127)]);
+        assert_eq!(
+            find_invalid(&input_generator(INPUT2), 4)[..4],
+            [(4, 47), (7, 55), (8, 65), (10, 102)]
+        );
+
+        // The two numbers must be different, and repeats in the window must not get lost
+        let mut v = XmasValidator::new(2);
+        assert!(v.push(5));
+        assert!(v.push(5));
+        assert!(!v.push(10));
+        assert!(v.push(15));
+        assert!(!v.push(30));
+        assert_eq!(v.invalid(), &[(2, 10), (4, 30)]);
+
+        // Pairs adding up past u64::MAX are skipped, on the way in and on the way out
+        assert_eq!(find_invalid(&[u64::MAX, 1, 5, 6], 2), vec![(2, 5)]);
+        assert!(find_invalid(&[u64::MAX - 1, 1, u64::MAX], 2).is_empty());
     }
 }
@@ -1,6 +1,7 @@
 #![feature(deque_range)]
 use aoc_runner_derive::aoc_lib;
 
+pub mod crt;
 pub mod day1;
 pub mod day10;
 pub mod day11;
@@ -11,6 +12,7 @@ pub mod day15;
 pub mod day16;
 pub mod day17;
 pub mod day18;
+pub mod day18repl;
 pub mod day19p1;
 pub mod day19p2;
 pub mod day2;
@@ -26,6 +28,8 @@ pub mod day5;
 pub mod day6;
 pub mod day7;
 pub mod day8;
+pub mod day8asm;
+pub mod day8debugger;
 pub mod day9;
 
 aoc_lib! { year = 2020 }